@@ -0,0 +1,47 @@
+use crate::util::IRect;
+
+///The part of the map that is on screen, follows the player
+#[derive(Debug, Clone, Copy)]
+pub struct Camera {
+    ///Where on the screen the map is drawn
+    pub viewport: IRect,
+    ///Map coordinates of the viewport's top left corner
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Camera {
+    pub fn new(viewport: IRect) -> Self {
+        Self { viewport, x: 0, y: 0 }
+    }
+
+    ///Centers on `(x, y)` without scrolling past the edges of `map`,
+    ///a map smaller than the viewport is centered in it
+    pub fn center_on(&mut self, x: i32, y: i32, map: IRect) {
+        let fit = |center: i32, view: i32, lo: i32, size: i32| match size > view {
+            true => (center - view / 2).clamp(lo, lo + size - view),
+            false => lo - (view - size) / 2,
+        };
+        self.x = fit(x, self.viewport.width(), map.x, map.width());
+        self.y = fit(y, self.viewport.height(), map.y, map.height());
+    }
+
+    ///The map tiles currently on screen
+    pub fn view(&self) -> IRect {
+        IRect::new(self.x, self.y, self.viewport.width(), self.viewport.height())
+    }
+
+    ///Screen position of a map tile, if it's on screen
+    pub fn to_screen(&self, x: i32, y: i32) -> Option<(i32, i32)> {
+        let (sx, sy) = (x - self.x + self.viewport.x, y - self.y + self.viewport.y);
+        match self.viewport.contains(sx, sy) {
+            true => Some((sx, sy)),
+            false => None,
+        }
+    }
+
+    ///Map tile under a screen position
+    pub fn to_map(&self, sx: i32, sy: i32) -> (i32, i32) {
+        (sx - self.viewport.x + self.x, sy - self.viewport.y + self.y)
+    }
+}
@@ -1,4 +1,4 @@
-use super::util::Glyph;
+use super::util::{Glyph, IRect};
 use macroquad::prelude::IVec2;
 
 use smallvec::{SmallVec, smallvec};
@@ -40,7 +40,7 @@ pub fn register_all_components(ecs: &mut World) {
     ecs.register::<Equipped>();
     ecs.register::<AttackBonus>();
     ecs.register::<DefenseBonus>();
-    ecs.register::<ParticleLifetime>();
+    ecs.register::<ParticleEmitter>();
     ecs.register::<HungerClock>();
     ecs.register::<Nutritious>();
     ecs.register::<MagicMapper>();
@@ -48,6 +48,35 @@ pub fn register_all_components(ecs: &mut World) {
     ecs.register::<EntryTrigger>();
     ecs.register::<EntityMoved>();
     ecs.register::<SingleActivation>();
+    ecs.register::<Asleep>();
+    ecs.register::<Unaware>();
+    ecs.register::<Sneaking>();
+    ecs.register::<MakesNoise>();
+    ecs.register::<IdleBehaviour>();
+    ecs.register::<Npc>();
+    ecs.register::<Stashed>();
+    ecs.register::<Value>();
+    ecs.register::<Wallet>();
+    ecs.register::<Gold>();
+    ecs.register::<Vendor>();
+    ecs.register::<Stack>();
+    ecs.register::<Weight>();
+    ecs.register::<CarryCapacity>();
+    ecs.register::<Encumbered>();
+    ecs.register::<Charges>();
+    ecs.register::<Recharger>();
+    ecs.register::<Mana>();
+    ecs.register::<Spell>();
+    ecs.register::<Spellbook>();
+    ecs.register::<KnownSpell>();
+    ecs.register::<Teleporter>();
+    ecs.register::<Pit>();
+    ecs.register::<FiresArrows>();
+    ecs.register::<SpawnsGas>();
+    ecs.register::<Gas>();
+    ecs.register::<Perception>();
+    ecs.register::<WantsToSearch>();
+    ecs.register::<WantsToDisarm>();
 
     ecs.register::<SimpleMarker<SerializeMe>>();
 }
@@ -76,6 +105,8 @@ pub struct Renderable {
     pub fg: [f32; 4],
     pub bg: [f32; 4],
     pub order: i32,
+    ///Drawn instead of the glyph in tile mode
+    pub sprite: Option<u16>,
 }
 
 #[derive(Component, Default, Serialize, Deserialize, Clone, Copy)]
@@ -104,6 +135,11 @@ impl Viewshed {
 #[storage(NullStorage)]
 pub struct Monster {}
 
+///Non-hostile inhabitants, e.g. in town
+#[derive(Component, Default, Serialize, Deserialize, Clone, Copy)]
+#[storage(NullStorage)]
+pub struct Npc {}
+
 #[derive(Component, ConvertSaveload, Clone)]
 pub struct Named(pub String);
 
@@ -226,9 +262,22 @@ pub struct DefenseBonus {
     pub defense: i32,
 }
 
-#[derive(Default, Component, Clone, Copy)]
-pub struct ParticleLifetime {
-    pub remaining_ms: f32,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParticleKind {
+    Smoke,
+    Fire,
+    Blood,
+    Trail,
+}
+
+///Keeps spawning particles of `kind` on its tile, tinted `color`
+#[derive(Component, Clone, Copy)]
+pub struct ParticleEmitter {
+    pub kind: ParticleKind,
+    pub color: [f32; 4],
+    pub interval_ms: f32,
+    ///Until the next particle
+    pub timer_ms: f32,
 }
 
 #[derive(Component, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
@@ -264,6 +313,189 @@ pub struct EntityMoved {}
 #[storage(NullStorage)]
 pub struct SingleActivation {}
 
+#[derive(Component, Default, Serialize, Deserialize, Clone, Copy)]
+#[storage(NullStorage)]
+pub struct Asleep {}
+
+///Awake, but hasn't noticed the player yet
+#[derive(Component, Default, Serialize, Deserialize, Clone, Copy)]
+#[storage(NullStorage)]
+pub struct Unaware {}
+
+#[derive(Component, ConvertSaveload, Clone, Copy)]
+pub struct Sneaking {
+    ///Set after a sneaking step, monsters get an extra turn to catch up
+    pub lagging: bool,
+}
+
+#[derive(Component, ConvertSaveload, Clone, Copy)]
+pub struct MakesNoise {
+    pub volume: i32,
+}
+
+///What a monster does while it isn't hunting the player
+#[derive(Component, Clone, Serialize, Deserialize)]
+pub enum IdleBehaviour {
+    Wander { area: IRect },
+    Patrol { waypoints: SmallVec<[(i32, i32); 4]>, next: usize },
+    Guard { post: (i32, i32) },
+}
+
+///Left behind on a level the player isn't on, in place of `Position`
+#[derive(Component, ConvertSaveload, Clone, Copy)]
+pub struct Stashed {
+    pub depth: i32,
+    pub x: i32,
+    pub y: i32,
+}
+
 
+///What an item is worth to a vendor
+#[derive(Component, ConvertSaveload, Clone, Copy)]
+pub struct Value {
+    pub gold: i32,
+}
+
+#[derive(Component, ConvertSaveload, Clone, Copy)]
+pub struct Wallet {
+    pub gold: i32,
+}
+
+///A pile of coins, goes straight into the wallet when picked up
+#[derive(Component, ConvertSaveload, Clone, Copy)]
+pub struct Gold {
+    pub amount: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Wares {
+    General,
+    Alchemy,
+    Smithing,
+}
+
+///Sells what's in its backpack and restocks every now and then
+#[derive(Component, Clone, Copy, Serialize, Deserialize)]
+pub struct Vendor {
+    pub wares: Wares,
+    pub restock_in: i32,
+}
+
+///Identical items carried around as a single entity
+#[derive(Component, ConvertSaveload, Clone, Copy)]
+pub struct Stack {
+    pub count: i32,
+}
+
+#[derive(Component, ConvertSaveload, Clone, Copy)]
+pub struct Weight {
+    pub lbs: i32,
+}
+
+///How much an entity carries before it's slowed down
+#[derive(Component, ConvertSaveload, Clone, Copy)]
+pub struct CarryCapacity {
+    pub lbs: i32,
+}
+
+impl CarryCapacity {
+    ///Nothing more can be picked up past this
+    pub fn limit(&self) -> i32 {
+        self.lbs * 3 / 2
+    }
+}
+
+///Carrying more than its `CarryCapacity`, steps take twice as long
+#[derive(Component, ConvertSaveload, Clone, Copy)]
+pub struct Encumbered {
+    pub lagging: bool,
+}
+
+///Uses left before the item goes inert
+#[derive(Component, ConvertSaveload, Clone, Copy)]
+pub struct Charges {
+    pub current: i32,
+    pub max: i32,
+}
+
+///Refills the `Charges` of everything the user carries
+#[derive(Component, Default, Serialize, Deserialize, Clone, Copy)]
+#[storage(NullStorage)]
+pub struct Recharger {}
+
+#[derive(Component, ConvertSaveload, Clone, Copy)]
+pub struct Mana {
+    pub current: i32,
+    pub max: i32,
+    pub regen_in: i32,
+}
+
+///Cast with the same effect components items use
+#[derive(Component, ConvertSaveload, Clone, Copy)]
+pub struct Spell {
+    pub cost: i32,
+    pub cooldown: i32,
+    pub ready_in: i32,
+}
+
+///Unread book, teaches the `Spell` on the same entity
+#[derive(Component, ConvertSaveload, Clone)]
+pub struct Spellbook {
+    pub spell: String,
+}
+
+#[derive(Component, ConvertSaveload, Clone, Copy)]
+pub struct KnownSpell {
+    pub owner: Entity,
+}
+
+#[derive(Component, Default, Serialize, Deserialize, Clone, Copy)]
+#[storage(NullStorage)]
+pub struct Teleporter {}
+
+///Drops whoever steps in to the next level
+#[derive(Component, Default, Serialize, Deserialize, Clone, Copy)]
+#[storage(NullStorage)]
+pub struct Pit {}
+
+///Shoots an arrow along a line through the trap, hitting the first creature on it
+#[derive(Component, Default, Serialize, Deserialize, Clone, Copy)]
+#[storage(NullStorage)]
+pub struct FiresArrows {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GasKind {
+    Poison,
+    Confusion,
+}
+
+#[derive(Component, Clone, Copy, Serialize, Deserialize)]
+pub struct SpawnsGas {
+    pub kind: GasKind,
+}
+
+///A tile of a gas cloud, spreads to neighbouring tiles while `spread` lasts
+#[derive(Component, Clone, Copy)]
+pub struct Gas {
+    pub kind: GasKind,
+    pub turns: i32,
+    pub spread: i32,
+}
+
+///How good someone is at spotting and disarming traps
+#[derive(Component, ConvertSaveload, Clone, Copy)]
+pub struct Perception {
+    pub bonus: i32,
+}
+
+///Looks for hidden traps around the player on the next visibility update
+#[derive(Component, Default, Serialize, Deserialize, Clone, Copy)]
+#[storage(NullStorage)]
+pub struct WantsToSearch {}
+
+#[derive(Component, ConvertSaveload, Clone, Copy)]
+pub struct WantsToDisarm {
+    pub trap: Entity,
+}
 
 pub struct SerializeMe {}
@@ -1,6 +1,8 @@
 use crate::{
     map::{TileType, TileFlags},
+    camera::Camera,
     screen::Screen,
+    sprites::SpriteSheet,
     util::{IRect, to_cp437, colors::*, Glyph},
 };
 
@@ -11,36 +13,77 @@ pub trait ViewMap {
     fn tile(&self, x: i32, y: i32) -> &TileType;
 }
 
-pub fn draw_map<M: ViewMap>(map: &M, s: &mut Screen) {
+///Draws with `sprites` in tile mode, glyphs otherwise
+pub fn draw_map<M: ViewMap>(map: &M, camera: &Camera, sprites: Option<&SpriteSheet>, 
+    s: &mut Screen) 
+{
     let bg = BLACK;
-    let floor_fg = [0.0, 0.5, 0.5, 1.0];
-    let wall_fg = [0.0, 1.0, 0.0, 1.0];
-    let stairs_fg = VIOLET;
+    let bounds = match map.bounds().intersection(&camera.view()) {
+        Some(bounds) => bounds,
+        None => return,
+    };
 
-    let bounds = map.bounds();
-
-    for y in 0..bounds.height() {
-        for x in 0..bounds.width() {
+    for y in bounds.y..=bounds.yy {
+        for x in bounds.x..=bounds.xx {
             let tile_status = map.tile_flags(x, y);
             if !tile_status.revealed { continue; }
 
-            let (mut fg, glyph) = match map.tile(x, y) {
-                TileType::Floor => (floor_fg, to_cp437('.')),
-                TileType::Wall => (wall_fg, wall_glyph(map, x, y)),
-                TileType::DownStairs => (stairs_fg, to_cp437('>'))
-            };
+            let (glyph, mut fg) = tile_glyph(map, x, y);
             let bg = match tile_status.bloodstained && tile_status.visible {
                 true => [0.75, 0., 0., 1.],
                 false => bg,
             };
             
             if !tile_status.visible { fg = greyscale(fg); }
-            s.draw_glyph(x, y, glyph, fg, bg);
+            let (sx, sy) = match camera.to_screen(x, y) {
+                Some(p) => p,
+                None => continue,
+            };
+            match sprites.and_then(|sheet| tile_sprite(sheet, map, x, y)) {
+                Some(sprite) => {
+                    let tint = match tile_status.visible {
+                        true => WHITE,
+                        false => GRAY,
+                    };
+                    s.draw_sprite(sx, sy, sprite, glyph, tint, bg);
+                },
+                None => s.draw_glyph(sx, sy, glyph, fg, bg),
+            }
         }
     }
 }
 
+///Glyph and foreground color of a revealed and visible tile
+pub fn tile_glyph<M: ViewMap>(map: &M, x: i32, y: i32) -> (Glyph, [f32; 4]) {
+    let floor_fg = [0.0, 0.5, 0.5, 1.0];
+    let wall_fg = [0.0, 1.0, 0.0, 1.0];
+    let stairs_fg = VIOLET;
+    let door_fg = BROWN;
+
+    match map.tile(x, y) {
+        TileType::Floor => (to_cp437('.'), floor_fg),
+        TileType::Wall => (wall_glyph(map, x, y), wall_fg),
+        TileType::DownStairs => (to_cp437('>'), stairs_fg),
+        TileType::UpStairs => (to_cp437('<'), stairs_fg),
+        TileType::Door => (to_cp437('+'), door_fg),
+    }
+}
+
+///Sprite of a revealed tile, walls are picked by their neighbours like glyphs are
+fn tile_sprite<M: ViewMap>(sheet: &SpriteSheet, map: &M, x: i32, y: i32) -> Option<u16> {
+    match map.tile(x, y) {
+        TileType::Wall => Some(sheet.walls[wall_mask(map, x, y)]),
+        tile => sheet.tiles.get(tile).copied(),
+    }
+}
+
 fn wall_glyph<M: ViewMap>(map: &M, x: i32, y: i32) -> Glyph {
+    [9, 186, 186, 186, 205, 188, 187, 185, 205, 200, 201,
+     204, 205, 202, 203, 206][wall_mask(map, x, y)]
+}
+
+///Which of the orthogonal neighbours are revealed walls, 1 up, 2 down, 4 left, 8 right
+fn wall_mask<M: ViewMap>(map: &M, x: i32, y: i32) -> usize {
     let bounds = map.bounds();
     
     let mut mask = 0;
@@ -51,8 +94,6 @@ fn wall_glyph<M: ViewMap>(map: &M, x: i32, y: i32) -> Glyph {
     if test(x, y + 1) { mask |= 2; }
     if test(x - 1, y) { mask |= 4; }
     if test(x + 1, y) { mask |= 8; }
-
-    [9, 186, 186, 186, 205, 188, 187, 185, 205, 200, 201,
-     204, 205, 202, 203, 206][mask]
+    mask
 }
 
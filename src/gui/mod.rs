@@ -3,7 +3,7 @@ mod ui_state;
 use std::io::Write;
 use std::io::Cursor;
 
-use macroquad::prelude::{IVec2, KeyCode, get_last_key_pressed};
+use macroquad::prelude::{IVec2, KeyCode, MouseButton, get_last_key_pressed, is_mouse_button_pressed};
 use smallvec::SmallVec;
 pub use menu::*;
 pub use ui_state::*;
@@ -12,17 +12,22 @@ pub use ui_state::*;
 use specs::prelude::*;
 use crate::{
     player::transform_movement_input,
-    screen::Screen,
+    screen::{Screen, Layer},
     comp::*, 
     util::{
         letter_to_option,
         GameLog, 
+        Span,
         IRect,
         colors::*,
         to_cp437,
         Glyph
     },
-    map::Map,
+    map::{Map, ViewMap},
+    camera::Camera,
+    trade,
+    inventory,
+    spells,
 };
 
 
@@ -31,28 +36,46 @@ pub enum ItemMenuResult {
     Cancel, 
     NoResponse, 
     Selected,
+    Page(usize),
 }
 
+///Rows taken by the panel at the bottom of the screen
+pub const PANEL_HEIGHT: i32 = 7;
+
 pub fn draw_ui(ecs: &World, s: &mut Screen) {
     let depth = ecs.fetch::<Map>().depth();
     let players = ecs.read_storage::<Player>();
     let stats = ecs.read_storage::<CombatStats>();
     let hunger_clock = ecs.read_storage::<HungerClock>();
-    let (stats, hc, _) = (&stats, &hunger_clock, &players).join().next().unwrap();
-
-    s.draw_box(IRect::new(0, 43, 80, 7), WHITE, BLACK);
-
-    s.draw_text(2, 43, YELLOW, BLACK, &format!("Depth: {}", depth));
-    s.draw_text(12, 43, YELLOW, BLACK,
+    let sneaking = ecs.read_storage::<Sneaking>();
+    let wallets = ecs.read_storage::<Wallet>();
+    let encumbered = ecs.read_storage::<Encumbered>();
+    let mana = ecs.read_storage::<Mana>();
+    let (stats, hc, sneaking, wallet, encumbered, mana, _) = (&stats, &hunger_clock, 
+        sneaking.maybe(), wallets.maybe(), encumbered.maybe(), mana.maybe(), &players)
+        .join().next().unwrap();
+
+    let (w, y) = (s.width(), s.height() - PANEL_HEIGHT);
+    s.draw_box(IRect::new(0, y, w, PANEL_HEIGHT), WHITE, BLACK);
+
+    match depth {
+        0 => s.draw_text(2, y, YELLOW, BLACK, "Town"),
+        _ => s.draw_text(2, y, YELLOW, BLACK, &format!("Depth: {}", depth)),
+    };
+    s.draw_text(12, y, YELLOW, BLACK,
         &format!("HP: {} / {}", stats.hp, stats.max_hp));
-    s.draw_bar_horizontal(28, 43, 51, stats.hp, 
+    s.draw_bar_horizontal(28, y, w - 29, stats.hp, 
         stats.max_hp, RED, BLACK);
 
     let log = ecs.fetch::<GameLog>();
-    let mut y = 44;
-    for entry in log.last_entries(5) {
-        s.draw_text(2, y, WHITE, [0.0; 4], entry);
-        y += 1;
+    let rows = PANEL_HEIGHT as usize - 2;
+    let mut lines: Vec<Vec<Span>> = vec![];
+    for entry in log.entries().iter().rev() {
+        if lines.len() >= rows { break; }
+        lines.extend(entry.wrap(w as usize - 4).into_iter().rev());
+    }
+    for (y, line) in (y + 1..).zip(lines.iter().take(rows).rev()) {
+        draw_spans(s, 2, y, line);
     }
 
     use HungerState::*;
@@ -62,72 +85,314 @@ pub fn draw_ui(ecs: &World, s: &mut Screen) {
         Hungry => ("Hungry", ORANGE),
         Starving => ("Starving", RED),
     };
-    s.draw_text(71, 42, fg, BLACK, text);
+    s.draw_text(w - 9, y - 1, fg, BLACK, text);
+
+    if sneaking.is_some() {
+        s.draw_text(w - 19, y - 1, SKYBLUE, BLACK, "Sneaking");
+    }
+    if encumbered.is_some() {
+        s.draw_text(35, y - 1, ORANGE, BLACK, "Overburdened");
+    }
+    if let Some(mana) = mana {
+        s.draw_text(2, y - 1, SKYBLUE, BLACK, &format!("MP: {} / {}", mana.current, mana.max));
+    }
+    s.draw_text(48, y - 1, GOLD, BLACK, &format!("Gold: {}", wallet.map_or(0, |w| w.gold)));
 }
 
-pub fn show_inventory(ecs: &World, title: &str, s: &mut Screen) -> (ItemMenuResult, Option<Entity>) {
-    let player_entity = ecs.fetch::<Entity>();
+///Names of what's on a tile, with their colors, hidden things left out
+pub fn tile_contents(ecs: &World, x: i32, y: i32) -> Vec<(String, [f32; 4])> {
+    let map = ecs.fetch::<Map>();
+    let names = ecs.read_storage::<Named>();
+    let hidden = ecs.read_storage::<Hidden>();
+    let renderables = ecs.read_storage::<Renderable>();
+    map.tile_content(x, y).iter()
+        .filter(|e| !hidden.contains(**e))
+        .filter_map(|e| {
+            let fg = renderables.get(*e).map_or(WHITE, |r| r.fg);
+            Some((names.get(*e)?.0.clone(), fg))
+        })
+        .collect()
+}
+
+///Lists what's on the visible tile under the mouse next to it
+pub fn draw_tooltip(ecs: &World, s: &mut Screen) {
+    let camera = ecs.fetch::<Camera>();
+    let (mx, my) = s.mouse_cell();
+    if !camera.viewport.contains(mx, my) { return; }
+    let (x, y) = camera.to_map(mx, my);
+    {
+        let map = ecs.fetch::<Map>();
+        if !map.bounds().contains(x, y) || !map.tile_flags(x, y).visible { return; }
+    }
+    let contents = tile_contents(ecs, x, y);
+    let width = match contents.iter().map(|(name, _)| name.chars().count()).max() {
+        Some(len) => len as i32 + 3,
+        None => return,
+    };
+    let height = contents.len() as i32 + 1;
+    let bx = if mx + 1 + width < s.width() { mx + 1 } else { mx - 1 - width };
+    let by = my.min(s.height() - PANEL_HEIGHT - height - 1).max(0);
+    s.draw_box(IRect::new(bx, by, width, height), WHITE, BLACK);
+    for (y, (name, fg)) in (by + 1..).zip(contents.iter()) {
+        s.draw_text(bx + 2, y, *fg, BLACK, name);
+    }
+}
+
+fn draw_spans(s: &mut Screen, mut x: i32, y: i32, spans: &[Span]) {
+    for span in spans {
+        s.draw_text(x, y, span.color, [0.0; 4], &span.text);
+        x += span.text.chars().count() as i32;
+    }
+}
+
+///Every message so far, `scroll` lines up from the newest.
+///Returns `Selected` when closed and the new scroll
+pub fn show_message_log(ecs: &World, s: &mut Screen, scroll: usize) -> (ItemMenuResult, usize) {
+    let log = ecs.fetch::<GameLog>();
+    let bounds = IRect::new(0, 0, s.width() - 1, s.height() - 1);
+    let rows = (bounds.height() - 2) as usize;
+    let lines: Vec<Vec<Span>> = log.entries().iter()
+        .flat_map(|entry| entry.wrap(bounds.width() as usize - 4))
+        .collect();
+    let max_scroll = lines.len().saturating_sub(rows);
+    let scroll = scroll.min(max_scroll);
+
+    s.draw_box(bounds, WHITE, BLACK);
+    s.draw_text(2, 0, YELLOW, BLACK, "Messages");
+    s.draw_text(2, bounds.yy, YELLOW, BLACK, 
+        "UP/DOWN or PAGE UP/PAGE DOWN to scroll, ESCAPE to close");
+    let end = lines.len() - scroll;
+    for (y, line) in (1..).zip(lines[end.saturating_sub(rows)..end].iter()) {
+        draw_spans(s, 2, y, line);
+    }
+
+    let page = rows - 1;
+    match get_last_key_pressed() {
+        Some(KeyCode::Escape | KeyCode::M) => (ItemMenuResult::Selected, 0),
+        Some(KeyCode::Up | KeyCode::K) => (ItemMenuResult::NoResponse, (scroll + 1).min(max_scroll)),
+        Some(KeyCode::Down | KeyCode::J) => (ItemMenuResult::NoResponse, scroll.saturating_sub(1)),
+        Some(KeyCode::PageUp) => (ItemMenuResult::NoResponse, (scroll + page).min(max_scroll)),
+        Some(KeyCode::PageDown) => (ItemMenuResult::NoResponse, scroll.saturating_sub(page)),
+        Some(KeyCode::Home) => (ItemMenuResult::NoResponse, max_scroll),
+        Some(KeyCode::End) => (ItemMenuResult::NoResponse, 0),
+        _ => (ItemMenuResult::NoResponse, scroll),
+    }
+}
+
+pub fn show_inventory(ecs: &World, title: &str, s: &mut Screen, page: usize) -> (ItemMenuResult, Option<Entity>) {
+    let player_entity = *ecs.fetch::<Entity>();
+    let key = get_last_key_pressed();
+    let selection = key.map_or(-1, letter_to_option);
+
+    let (selected_itm, pages) = item_list(ecs, s, player_entity, title, "ESCAPE to cancel", 
+        None, page, selection);
+    match key {
+        Some(KeyCode::Escape) => (ItemMenuResult::Cancel, None),
+        Some(key) if turn_page(key, page, pages).is_some() => 
+            (ItemMenuResult::Page(turn_page(key, page, pages).unwrap()), None),
+        _ if selected_itm.is_some() => (ItemMenuResult::Selected, selected_itm),
+        _ if is_mouse_button_pressed(MouseButton::Right) => (ItemMenuResult::Cancel, None),
+        _ => (ItemMenuResult::NoResponse, None),
+    }
+}
+
+pub enum TradeResult {
+    Idle,
+    Leave,
+    Switch,
+    Page(usize),
+    Selected(Entity),
+}
+
+pub fn show_trade(ecs: &World, s: &mut Screen, vendor: Entity, selling: bool, page: usize) -> TradeResult {
+    let player_entity = *ecs.fetch::<Entity>();
+    let key = get_last_key_pressed();
+    let selection = key.map_or(-1, letter_to_option);
+
+    let gold = ecs.read_storage::<Wallet>().get(player_entity).map_or(0, |w| w.gold);
+    let vendor_name = ecs.read_storage::<Named>().get(vendor)
+        .map_or_else(String::new, |n| n.0.clone());
+    let values = ecs.read_storage::<Value>();
+    let (owner, title, footer) = match selling {
+        false => (vendor, format!("Buy from the {} ({} gold)", vendor_name, gold), 
+            "TAB to sell, ESCAPE to leave"),
+        true => (player_entity, format!("Sell to the {} ({} gold)", vendor_name, gold), 
+            "TAB to buy, ESCAPE to leave"),
+    };
+    let price = |e: Entity| values.get(e).map(|v| match selling {
+        false => trade::buy_price(v),
+        true => trade::sell_price(v),
+    });
+
+    let (selected_itm, pages) = item_list(ecs, s, owner, &title, footer, Some(&price), 
+        page, selection);
+    match key {
+        Some(KeyCode::Escape) => TradeResult::Leave,
+        Some(KeyCode::Tab) => TradeResult::Switch,
+        Some(key) => match (turn_page(key, page, pages), selected_itm) {
+            (Some(page), _) => TradeResult::Page(page),
+            (None, Some(item)) => TradeResult::Selected(item),
+            _ => TradeResult::Idle,
+        },
+        None => match selected_itm {
+            Some(item) => TradeResult::Selected(item),
+            None => TradeResult::Idle,
+        },
+    }
+}
+
+///The page `key` flips the item list to, if it's a paging key
+fn turn_page(key: KeyCode, page: usize, pages: usize) -> Option<usize> {
+    match key {
+        KeyCode::PageDown | KeyCode::Right => Some((page + 1).min(pages - 1)),
+        KeyCode::PageUp | KeyCode::Left => Some(page.saturating_sub(1)),
+        _ => None,
+    }
+}
+
+const ITEMS_PER_PAGE: usize = 26;
+
+///Draws a page of `owner`'s items grouped by name, with prices if there are any.
+///Returns the one `selection` points at or that was clicked, and the number of pages
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn item_list(ecs: &World, s: &mut Screen, owner: Entity, title: &str, footer: &str,
+    prices: Option<&dyn Fn(Entity) -> Option<i32>>, page: usize, selection: i32) -> (Option<Entity>, usize) 
+{
     let named = ecs.read_storage::<Named>();
     let backpacked = ecs.read_storage::<InBackpack>();
+    let stacks = ecs.read_storage::<Stack>();
+    let charges = ecs.read_storage::<Charges>();
     let entities = ecs.entities();
 
-    let (result, selection) = match get_last_key_pressed() {
-        Some(KeyCode::Escape) => (ItemMenuResult::Cancel, -1),
-        Some(key) => (ItemMenuResult::Selected, letter_to_option(key)),
-        None => (ItemMenuResult::NoResponse, -1),
-    };
-
-    let mut items: SmallVec<[(Entity, &str); 64]> = 
-    (&entities, &backpacked, &named)
+    let mut items: SmallVec<[(Entity, &str, i32, Option<&Charges>); 64]> = 
+    (&entities, &backpacked, &named, stacks.maybe(), charges.maybe())
         .join()
-        .filter(|(_, itm, _)| itm.owner == *player_entity)
-        .map(|(e, _, name)| (e, name.0.as_str()))
+        .filter(|(_, itm, _, _, _)| itm.owner == owner)
+        .map(|(e, _, name, stack, ch)| (e, name.0.as_str(), stack.map_or(1, |s| s.count), ch))
         .collect();
-    items.sort_unstable_by_key(|(_, x)| *x);
+    items.sort_unstable_by_key(|(_, x, _, ch)| (*x, ch.map(|ch| ch.current)));
     let mut item_counts: SmallVec<[(usize, i32); 32]> = SmallVec::new();
 
-    if !items.is_empty() { item_counts.push((0, 1)); }
-    for i in 1..items.len() {
-        if items[i - 1].1 == items[i].1 {
-            item_counts.last_mut().unwrap().1 += 1;
+    //Charged items are listed one by one, since their charges differ
+    for i in 0..items.len() {
+        if i > 0 && items[i - 1].1 == items[i].1 && items[i].3.is_none() {
+            item_counts.last_mut().unwrap().1 += items[i].2;
             continue;
         }
-        item_counts.push((i, 1));
+        item_counts.push((i, items[i].2));
     }
 
-    let num_entries = item_counts.len() as i32;
-    let mut y = (25 - (num_entries / 2)) as i32;
-    s.draw_box(IRect::new(15, y - 1, 31, num_entries+2), WHITE, BLACK);
+    let pages = item_counts.len().div_ceil(ITEMS_PER_PAGE).max(1);
+    let page = page.min(pages - 1);
+    let entries = item_counts.iter().skip(page * ITEMS_PER_PAGE).take(ITEMS_PER_PAGE);
+    let num_entries = entries.len() as i32;
+    let width = if prices.is_some() { 41 } else { 31 };
+    let y = 25 - (num_entries / 2);
+    s.draw_box(IRect::new(15, y - 1, width, num_entries+2), WHITE, BLACK);
     s.draw_text(18, y-2, YELLOW, BLACK, title);
-    s.draw_text(18, y + num_entries + 1, YELLOW, BLACK, "ESCAPE to cancel");
+    s.draw_text(18, y + num_entries + 1, YELLOW, BLACK, footer);
+    if pages > 1 {
+        let text = format!("< {}/{} >", page + 1, pages);
+        s.draw_text(15 + width - 2 - text.len() as i32, y + num_entries + 1, YELLOW, BLACK, &text);
+    }
+    if let Some(capacity) = ecs.read_storage::<CarryCapacity>().get(owner) {
+        let carried = inventory::carried_weight(owner, &backpacked, &ecs.read_storage::<Equipped>(),
+            &ecs.read_storage::<Weight>(), &stacks);
+        let text = format!("{}/{} lbs", carried, capacity.lbs);
+        let fg = if carried > capacity.lbs { ORANGE } else { YELLOW };
+        s.draw_text(15 + width - 2 - text.len() as i32, y - 2, fg, BLACK, &text);
+    }
 
     let mut selected_itm = None;
     let mut buf = [0u8; 64];
-    for (i, (idx, cnt)) in item_counts.iter().enumerate() {
-        let (entity, name) = items[*idx];
-        s.draw_glyph(17, y, to_cp437('['), WHITE, BLACK);
-        s.draw_glyph(18, y, 97 + i as Glyph, WHITE, BLACK);
-        s.draw_glyph(19, y, to_cp437(']'), WHITE, BLACK);
+    let (mx, my) = s.mouse_cell();
+    let clicked = is_mouse_button_pressed(MouseButton::Left);
+    for (y, (i, (idx, cnt))) in (y..).zip(entries.enumerate()) {
+        let (entity, name, _, charges) = items[*idx];
+        let hovered = my == y && (16..15 + width).contains(&mx);
+        if hovered {
+            for x in 16..15 + width {
+                s.set_bg(x, y, DARKGRAY);
+            }
+        }
+        let bg = if hovered { DARKGRAY } else { BLACK };
+        s.draw_glyph(17, y, to_cp437('['), WHITE, bg);
+        s.draw_glyph(18, y, 97 + i as Glyph, WHITE, bg);
+        s.draw_glyph(19, y, to_cp437(']'), WHITE, bg);
 
         let mut cursor = Cursor::new(&mut buf[..]);
         write!(cursor, "{}", name).unwrap();
         if *cnt > 1 {
             write!(cursor, " ({})", *cnt).unwrap();
         }
+        if let Some(ch) = charges {
+            write!(cursor, " [{}/{}]", ch.current, ch.max).unwrap();
+        }
         let cursor = cursor.position() as usize;
         let text = std::str::from_utf8(&buf[..cursor]).unwrap();
-        s.draw_text(21, y, WHITE, BLACK, text);
+        s.draw_text(21, y, WHITE, bg, text);
 
-        y += 1;
-        if selection == i as i32 {
+        if let Some(prices) = prices {
+            let price = prices(entity).map_or_else(|| "-".to_owned(), |p| format!("{}g", p));
+            s.draw_text(15 + width - 2 - price.len() as i32, y, GOLD, bg, &price);
+        }
+
+        if selection == i as i32 || hovered && clicked {
             selected_itm = Some(entity);
         }
     }
+    (selected_itm, pages)
+}
 
-    match result {
-        ItemMenuResult::Selected if selected_itm.is_none() => 
-            (ItemMenuResult::NoResponse, None),
-        result => (result, selected_itm)
+pub fn show_spells(ecs: &World, s: &mut Screen) -> (ItemMenuResult, Option<Entity>) {
+    let player_entity = *ecs.fetch::<Entity>();
+    let named = ecs.read_storage::<Named>();
+    let known = ecs.read_storage::<KnownSpell>();
+    let spells = ecs.read_storage::<Spell>();
+    let mana = ecs.read_storage::<Mana>();
+    let mana = mana.get(player_entity);
+    let entities = ecs.entities();
+
+    let mut list: SmallVec<[(Entity, &str, &Spell); 16]> = (&entities, &known, &named, &spells)
+        .join()
+        .filter(|(_, k, _, _)| k.owner == player_entity)
+        .map(|(e, _, name, spell)| (e, name.0.as_str(), spell))
+        .collect();
+    list.sort_unstable_by_key(|(_, name, _)| *name);
+
+    let num_entries = list.len() as i32;
+    let y = 25 - (num_entries / 2);
+    s.draw_box(IRect::new(15, y - 1, 31, num_entries + 2), WHITE, BLACK);
+    s.draw_text(18, y - 2, YELLOW, BLACK, "Cast which spell?");
+    s.draw_text(18, y + num_entries + 1, YELLOW, BLACK, "ESCAPE to cancel");
+    if list.is_empty() {
+        s.draw_text(17, y, GRAY, BLACK, "You don't know any spells");
+    }
+
+    let selection = get_last_key_pressed();
+    let mut selected = None;
+    for (y, (i, (e, name, spell))) in (y..).zip(list.iter().enumerate()) {
+        let fg = if spells::castable(spell, mana).is_ok() { WHITE } else { GRAY };
+        s.draw_glyph(17, y, to_cp437('['), WHITE, BLACK);
+        s.draw_glyph(18, y, 97 + i as Glyph, WHITE, BLACK);
+        s.draw_glyph(19, y, to_cp437(']'), WHITE, BLACK);
+        s.draw_text(21, y, fg, BLACK, name);
+
+        let cost = match spell.ready_in {
+            0 => format!("{} mp", spell.cost),
+            n => format!("{} mp ({})", spell.cost, n),
+        };
+        s.draw_text(44 - cost.len() as i32, y, SKYBLUE, BLACK, &cost);
+
+        if selection.map(letter_to_option) == Some(i as i32) {
+            selected = Some(*e);
+        }
+    }
+
+    match selection {
+        Some(KeyCode::Escape) => (ItemMenuResult::Cancel, None),
+        Some(_) if selected.is_some() => (ItemMenuResult::Selected, selected),
+        _ => (ItemMenuResult::NoResponse, None),
     }
 }
 
@@ -137,6 +402,7 @@ pub fn show_examiner(ecs: &World, s: &mut Screen, mut pos: IVec2, range: Option<
     let viewsheds = ecs.read_storage::<Viewshed>();
     let viewshed = viewsheds.get(*player_entity).unwrap();
     let range = range.unwrap_or(viewshed.range);
+    let camera = ecs.fetch::<Camera>();
 
     let d = plp - pos;
     if d.dot(d) > range * range {
@@ -145,12 +411,30 @@ pub fn show_examiner(ecs: &World, s: &mut Screen, mut pos: IVec2, range: Option<
 
 
     let mut result = ItemMenuResult::NoResponse;
+    let can_pick = |dst: IVec2| {
+        let d = plp - dst;
+        d.dot(d) <= range * range && viewshed.can_see(dst.x, dst.y)
+    };
+
+    let (mx, my) = s.mouse_cell();
+    if camera.viewport.contains(mx, my) {
+        let (x, y) = camera.to_map(mx, my);
+        let hovered = IVec2::new(x, y);
+        if s.mouse_moved() && can_pick(hovered) {
+            pos = hovered;
+        }
+        if is_mouse_button_pressed(MouseButton::Left) && hovered == pos {
+            result = ItemMenuResult::Selected;
+        }
+    }
+    if is_mouse_button_pressed(MouseButton::Right) {
+        result = ItemMenuResult::Cancel;
+    }
 
     if let Some(key) = get_last_key_pressed() {
         if let Some((dx, dy)) = transform_movement_input(key) {
             let dst = IVec2::new(dx, dy) + pos;
-            let d = plp - dst;
-            if d.dot(d) <= range *range && viewshed.can_see(dst.x, dst.y) {
+            if can_pick(dst) {
                 pos = dst;
             }
         }
@@ -161,14 +445,19 @@ pub fn show_examiner(ecs: &World, s: &mut Screen, mut pos: IVec2, range: Option<
         }
     }
 
+    s.set_layer(Layer::Effects);
     for pt in viewshed.visible_tiles.iter() {
         let d = IVec2::new(pt.0, pt.1) - plp;
-        if d.dot(d) <= range * range {
-            s.set_bg(pt.0, pt.1, GRAY);
+        if d.dot(d) > range * range { continue; }
+        if let Some((sx, sy)) = camera.to_screen(pt.0, pt.1) {
+            s.set_bg(sx, sy, [GRAY[0], GRAY[1], GRAY[2], 0.5]);
         }
     }
 
-    s.set_bg(pos.x, pos.y, BLUE);
+    if let Some((sx, sy)) = camera.to_screen(pos.x, pos.y) {
+        s.set_bg(sx, sy, [BLUE[0], BLUE[1], BLUE[2], 0.7]);
+    }
+    s.set_layer(Layer::Ui);
 
     (result, pos)
 }
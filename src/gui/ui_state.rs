@@ -1,6 +1,6 @@
 use specs::prelude::*;
-use macroquad::prelude::IVec2;
-use smallvec::{smallvec, SmallVec};
+use macroquad::prelude::{IVec2, KeyCode, is_key_pressed};
+use smallvec::SmallVec;
 use std::io::Write;
 
 use super::*;
@@ -9,61 +9,82 @@ use crate::{
     screen::Screen,
     state::RunState,
     save_load,
+    trade,
+    inventory,
+    spells,
+    travel,
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum UIState {
-    UseItem,
-    DropItem,
+    UseItem(usize),
+    DropItem(usize),
+    CastSpell,
     Examine(IVec2),
+    ///Lines scrolled up from the newest
+    MessageLog(usize),
     Target { range: i32, item: Entity, pos: IVec2 },
+    Trade { vendor: Entity, selling: bool, page: usize },
     MainMenu(MainMenuSelection),
 }
 
 pub fn handle_state(state: UIState, ecs: &mut World, s: &mut Screen) -> RunState {
     use UIState::*;
     match state {
-        UseItem => match show_inventory(ecs, "Inventory", s) {
+        UseItem(page) => match show_inventory(ecs, "Inventory", s, page) {
             (ItemMenuResult::Cancel, _) => RunState::AwaitingInput,
+            (ItemMenuResult::Page(page), _) => RunState::UI(UseItem(page)),
             (ItemMenuResult::Selected, Some(item)) => {
-                let ranged = ecs.read_storage::<Ranged>();
-                let plp = *ecs.fetch::<IVec2>();
-                if let Some(ranged) = ranged.get(item) {
-                    RunState::UI(Target { item, range: ranged.range, pos: plp })
-                } else {
-                    ecs.write_storage::<WantsToUseItem>()
-                        .insert(*ecs.fetch::<Entity>(), WantsToUseItem { item, target: UseTarget::User })
-                        .expect("unable to insert intent");
+                if ecs.read_storage::<Spellbook>().contains(item) {
+                    let player = *ecs.fetch::<Entity>();
+                    spells::learn(ecs, player, item);
                     RunState::PlayerTurn
+                } else {
+                    use_item(ecs, item)
                 }
             }
-            _ => RunState::UI(UseItem)
+            _ => RunState::UI(UseItem(page))
         },
-        DropItem => match show_inventory(ecs, "Drop which item?", s) {
+        DropItem(page) => match show_inventory(ecs, "Drop which item?", s, page) {
             (ItemMenuResult::Cancel, _) => RunState::AwaitingInput,
+            (ItemMenuResult::Page(page), _) => RunState::UI(DropItem(page)),
             (ItemMenuResult::Selected, Some(item)) => {
+                let item = inventory::split_stack(ecs, item, 1);
                 ecs.write_storage::<WantsToDropItem>()
                     .insert(*ecs.fetch::<Entity>(), WantsToDropItem { item })
                     .expect("unable to insert intent");
 
                 RunState::PlayerTurn
             }
-            _ => RunState::UI(DropItem)
+            _ => RunState::UI(DropItem(page))
+        },
+        CastSpell => match show_spells(ecs, s) {
+            (ItemMenuResult::Cancel, _) => RunState::AwaitingInput,
+            (ItemMenuResult::Selected, Some(spell)) => {
+                let player = *ecs.fetch::<Entity>();
+                let ready = spells::castable(ecs.read_storage::<Spell>().get(spell).unwrap(), 
+                    ecs.read_storage::<Mana>().get(player));
+                match ready {
+                    Ok(()) => use_item(ecs, spell),
+                    Err(reason) => {
+                        let name = ecs.read_storage::<Named>().get(spell).unwrap().0.clone();
+                        write!(ecs.fetch_mut::<GameLog>().new_entry(), "{} {}.", name, reason).unwrap();
+                        RunState::AwaitingInput
+                    },
+                }
+            }
+            _ => RunState::UI(CastSpell)
         },
         Examine(initial) => match show_examiner(ecs, s, initial, None) {
+            (ItemMenuResult::NoResponse, epos) if is_key_pressed(KeyCode::T) =>
+                travel::travel_to(ecs, epos),
             (ItemMenuResult::Selected, epos) => {
+                let contents = tile_contents(ecs, epos.x, epos.y);
+                let items: SmallVec<[&str; 8]> = contents.iter()
+                    .map(|(name, _)| name.as_str())
+                    .collect();
                 let mut log = ecs.fetch_mut::<GameLog>();
                 let map = ecs.fetch::<Map>();
-                let names = ecs.read_storage::<Named>();
-                let hidden = ecs.read_storage::<Hidden>();
-                let mut items: SmallVec<[&str; 8]> = smallvec![];
-
-                for e in map.tile_content(epos.x, epos.y) {
-                    if hidden.contains(*e) { continue; }
-                    if let Some(name) = names.get(*e) {
-                        items.push(&name.0);
-                    }
-                }
 
                 let mut entry = log.new_entry();
                 match items.len() {
@@ -84,6 +105,10 @@ pub fn handle_state(state: UIState, ecs: &mut World, s: &mut Screen) -> RunState
             (ItemMenuResult::NoResponse, epos) => RunState::UI(Examine(epos)),
             _ => RunState::AwaitingInput,
         }
+        MessageLog(scroll) => match show_message_log(ecs, s, scroll) {
+            (ItemMenuResult::NoResponse, scroll) => RunState::UI(MessageLog(scroll)),
+            _ => RunState::AwaitingInput,
+        },
         Target { range, item, pos } =>  {
             match ranged_target(ecs, s, range, pos) {
                 (ItemMenuResult::Selected, pos) => {
@@ -98,6 +123,19 @@ pub fn handle_state(state: UIState, ecs: &mut World, s: &mut Screen) -> RunState
                 _ => RunState::AwaitingInput,
             }
         }
+        Trade { vendor, selling, page } => match show_trade(ecs, s, vendor, selling, page) {
+            TradeResult::Idle => RunState::UI(Trade { vendor, selling, page }),
+            TradeResult::Leave => RunState::AwaitingInput,
+            TradeResult::Switch => RunState::UI(Trade { vendor, selling: !selling, page: 0 }),
+            TradeResult::Page(page) => RunState::UI(Trade { vendor, selling, page }),
+            TradeResult::Selected(item) => {
+                match selling {
+                    false => trade::buy(ecs, vendor, item),
+                    true => trade::sell(ecs, vendor, item),
+                };
+                RunState::UI(Trade { vendor, selling, page })
+            },
+        },
         MainMenu(current) => match main_menu(ecs, s, current) {
             MainMenuResult::Idle(selection) => RunState::UI(MainMenu(selection)),
             MainMenuResult::Selected(selection) => match selection {
@@ -113,3 +151,16 @@ pub fn handle_state(state: UIState, ecs: &mut World, s: &mut Screen) -> RunState
     }
 }
 
+///Uses an item or casts a spell, picking a target first if it's ranged
+fn use_item(ecs: &mut World, item: Entity) -> RunState {
+    let ranged = ecs.read_storage::<Ranged>();
+    let plp = *ecs.fetch::<IVec2>();
+    if let Some(ranged) = ranged.get(item) {
+        RunState::UI(UIState::Target { item, range: ranged.range, pos: plp })
+    } else {
+        ecs.write_storage::<WantsToUseItem>()
+            .insert(*ecs.fetch::<Entity>(), WantsToUseItem { item, target: UseTarget::User })
+            .expect("unable to insert intent");
+        RunState::PlayerTurn
+    }
+}
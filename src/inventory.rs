@@ -0,0 +1,112 @@
+use std::ops::Deref;
+use specs::{prelude::*, storage::MaskedStorage, saveload::{MarkedBuilder, SimpleMarker}};
+use crate::{comp::*, save_load::with_saved_components};
+
+///Total weight of everything `owner` has in its backpack or equipped
+pub fn carried_weight<B, Q, W, S>(
+    owner: Entity,
+    backpacks: &Storage<InBackpack, B>,
+    equipped: &Storage<Equipped, Q>,
+    weights: &Storage<Weight, W>,
+    stacks: &Storage<Stack, S>,
+) -> i32 
+where 
+    B: Deref<Target = MaskedStorage<InBackpack>>,
+    Q: Deref<Target = MaskedStorage<Equipped>>,
+    W: Deref<Target = MaskedStorage<Weight>>,
+    S: Deref<Target = MaskedStorage<Stack>>,
+{
+    (backpacks.maybe(), equipped.maybe(), weights, stacks.maybe()).join()
+        .filter(|(bp, eq, _, _)| bp.map(|bp| bp.owner) == Some(owner) 
+            || eq.map(|eq| eq.owner) == Some(owner))
+        .map(|(_, _, w, stack)| w.lbs * stack.map_or(1, |s| s.count))
+        .sum()
+}
+
+///Whether `owner` can take `item` without going over its `CarryCapacity` limit
+pub fn can_carry(ecs: &World, owner: Entity, item: Entity) -> bool {
+    let capacity = match ecs.read_storage::<CarryCapacity>().get(owner) {
+        Some(capacity) => *capacity,
+        None => return true,
+    };
+    let stacks = ecs.read_storage::<Stack>();
+    let weights = ecs.read_storage::<Weight>();
+    let carried = carried_weight(owner, &ecs.read_storage::<InBackpack>(),
+        &ecs.read_storage::<Equipped>(), &weights, &stacks);
+    let weight = weights.get(item).map_or(0, |w| w.lbs) 
+        * stacks.get(item).map_or(1, |s| s.count);
+    carried + weight <= capacity.limit()
+}
+
+///Another stack in the same backpack `item` could be merged into
+pub fn find_stack<B, N, S>(
+    item: Entity,
+    owner: Entity,
+    entities: &Entities,
+    backpacks: &Storage<InBackpack, B>,
+    named: &Storage<Named, N>,
+    stacks: &Storage<Stack, S>,
+) -> Option<Entity>
+where 
+    B: Deref<Target = MaskedStorage<InBackpack>>,
+    N: Deref<Target = MaskedStorage<Named>>,
+    S: Deref<Target = MaskedStorage<Stack>>,
+{
+    if !stacks.contains(item) { return None; }
+    let name = &named.get(item)?.0;
+    (entities, backpacks, named, stacks).join()
+        .find(|(e, bp, n, _)| *e != item && bp.owner == owner && &n.0 == name)
+        .map(|(e, _, _, _)| e)
+}
+
+///Merges `item` into a matching stack in its owner's backpack, 
+///returns the entity the item ended up as
+pub fn restack(ecs: &mut World, item: Entity) -> Entity {
+    let target = {
+        let backpacks = ecs.read_storage::<InBackpack>();
+        let owner = match backpacks.get(item) {
+            Some(bp) => bp.owner,
+            None => return item,
+        };
+        find_stack(item, owner, &ecs.entities(), &backpacks, 
+            &ecs.read_storage::<Named>(), &ecs.read_storage::<Stack>())
+    };
+    match target {
+        Some(target) => {
+            let mut stacks = ecs.write_storage::<Stack>();
+            let count = stacks.get(item).unwrap().count;
+            stacks.get_mut(target).unwrap().count += count;
+            drop(stacks);
+            ecs.delete_entity(item).expect("failed to delete merged stack");
+            target
+        },
+        None => item,
+    }
+}
+
+///Copies every component of type `$t` that `$from` has over to `$to`
+macro_rules! copy_components {
+    ($ecs:expr, $from:expr, $to:expr, $($t:ty),* $(,)?) => { $(
+        let c = $ecs.read_storage::<$t>().get($from).cloned();
+        if let Some(c) = c {
+            $ecs.write_storage::<$t>().insert($to, c).expect("failed to copy component");
+        }
+    )* };
+}
+
+///Takes `count` items off the stack into a new entity in the same place,
+///or returns `item` itself if that's all of it
+pub fn split_stack(ecs: &mut World, item: Entity, count: i32) -> Entity {
+    match ecs.write_storage::<Stack>().get_mut(item) {
+        Some(stack) if stack.count > count => stack.count -= count,
+        _ => return item,
+    }
+
+    let split = ecs.create_entity()
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build();
+    with_saved_components!(copy_components!(ecs, item, split));
+    ecs.write_storage::<Stack>().insert(split, Stack { count })
+        .expect("failed to insert Stack");
+    split
+}
@@ -21,28 +21,76 @@ pub mod player;
 pub mod random_table;
 pub mod map_builder;
 pub mod draw_map;
+pub mod camera;
+pub mod settings;
+pub mod sprites;
+pub mod trade;
+pub mod travel;
+pub mod inventory;
+pub mod spells;
 
 use macroquad::prelude::*;
 
-use screen::Screen;
+use screen::{Screen, Font, MIN_COLS, MIN_ROWS};
+use settings::Settings;
 use state::State;
 
-fn window_conf() -> Conf {
+fn window_conf(settings: &Settings) -> Conf {
+    let tileset = settings.tileset.tileset();
     Conf {
         window_title: "Roguelike".to_owned(),
-        window_width: 80 * 16,
-        window_height: 50 * 16,
-        window_resizable: false,
+        window_width: (MIN_COLS as f32 * tileset.glyph_width as f32 * settings.scale) as i32,
+        window_height: (MIN_ROWS as f32 * tileset.glyph_height as f32 * settings.scale) as i32,
+        window_resizable: true,
         ..Default::default()
     }
 }
 
-#[macroquad::main(window_conf)]
-async fn main() {
-    let texture = load_texture("atlas.png").await.unwrap();
-    let screen = Screen::new(80, 50, texture, 16, 16, Vec2::new(1., 1.));
+///`roguelike --mapgen-stats [runs] [max depth]` prints map generation statistics,
+///`roguelike --export-map <recipe|file.txt> <depth> <name>` writes a level to
+///`<name>.txt` and `<name>.png`. Without arguments the game starts.
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let arg = |i: usize| args.get(i).map(String::as_str);
+    let prefabs = || std::rc::Rc::new(map_builder::load_prefabs("prefabs.json"));
+    match arg(1) {
+        Some("--mapgen-stats") => {
+            let runs = arg(2).and_then(|s| s.parse().ok()).unwrap_or(100);
+            let max_depth = arg(3).and_then(|s| s.parse().ok()).unwrap_or(5);
+            let depths: Vec<i32> = (1..=max_depth).collect();
+            map_builder::print_mapgen_stats(state::MAP_WIDTH, state::MAP_HEIGHT, 
+                &depths, runs, prefabs());
+            return;
+        },
+        Some("--export-map") => {
+            let source = arg(2).unwrap_or("Caves");
+            let depth = arg(3).and_then(|s| s.parse().ok()).unwrap_or(1);
+            let name = arg(4).unwrap_or("map_export");
+            map_builder::export_generated(state::MAP_WIDTH, state::MAP_HEIGHT, 
+                source, depth, name, prefabs());
+            return;
+        },
+        _ => (),
+    }
+
+    let settings = Settings::load(settings::SETTINGS_FILE);
+    macroquad::Window::from_config(window_conf(&settings), amain(settings));
+}
+
+async fn amain(settings: Settings) {
+    let tiles = Font::load(&settings.tileset.tileset()).await;
+    let text = settings.text_tileset.as_ref().unwrap_or(&settings.tileset).tileset();
+    let text = Font::load(&text).await;
+    let sheet = settings.sprites.as_deref()
+        .filter(|path| std::path::Path::new(path).exists())
+        .map(sprites::SpriteSheet::load);
+    let sprite_font = match &sheet {
+        Some(sheet) => Some(Font::load(&sheet.tileset()).await),
+        None => None,
+    };
+    let screen = Screen::new(tiles, text, sprite_font, settings.scale);
 
-    let mut state = State::new(screen);
+    let mut state = State::new(screen, sheet, settings.wait_for_animations);
 
     while state.tick() {
         next_frame().await
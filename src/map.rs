@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use specs::Entity;
 use serde::{Serialize, Deserialize};
 use macroquad::prelude::IVec2;
@@ -14,11 +15,23 @@ use super::{
 
 pub use crate::draw_map::ViewMap;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum TileType {
     Floor,
     Wall,
     DownStairs,
+    UpStairs,
+    Door,
+}
+
+impl TileType {
+    pub fn is_walkable(&self) -> bool {
+        !matches!(self, TileType::Wall)
+    }
+
+    pub fn is_opaque(&self) -> bool {
+        matches!(self, TileType::Wall | TileType::Door)
+    }
 }
 
 #[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
@@ -27,6 +40,9 @@ pub struct TileFlags {
     pub visible: bool,
     pub blocked: bool,
     pub bloodstained: bool,
+    ///A trap the player has revealed. Monsters won't step there either: they share 
+    ///what the player knows about traps rather than keeping track of their own
+    pub known_trap: bool,
 }
 
 impl TileFlags {
@@ -71,12 +87,15 @@ impl Map {
         for content in self.tile_content.iter_mut() {
             content.clear();
         }
+        for flags in self.tile_flags.iter_mut() {
+            flags.known_trap = false;
+        }
     }
 
     pub fn populate_blocked(&mut self) {
         let it_flags = self.tile_flags.iter_mut();
         for (flags, tile) in it_flags.zip(self.tiles.iter()) {
-            flags.blocked = *tile == TileType::Wall;
+            flags.blocked = !tile.is_walkable();
         }
     }
 
@@ -107,7 +126,8 @@ impl Map {
     }
     
     pub fn is_exit_valid(&self, x: i32, y: i32) -> bool {
-        self.bounds().contains(x, y) && !self.tile_flags(x, y).blocked
+        self.bounds().contains(x, y) && !self.tile_flags(x, y).blocked 
+            && !self.tile_flags(x, y).known_trap
     }
 
     pub fn adjacent(&self, x: i32, y: i32) -> impl Iterator<Item = (i32, i32)> + '_ {
@@ -116,6 +136,24 @@ impl Map {
     }
 }
 
+///Levels the player has left but can come back to
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct LevelStash {
+    maps: HashMap<i32, Map>,
+}
+
+impl LevelStash {
+    pub fn stash(&mut self, map: Map) {
+        self.maps.insert(map.depth(), map);
+    }
+
+    pub fn take(&mut self, depth: i32) -> Option<Map> {
+        let mut map = self.maps.remove(&depth)?;
+        map.realloc_content_index();
+        Some(map)
+    }
+}
+
 impl ViewMap for Map {
     fn tile(&self, x: i32, y: i32) -> &TileType {
         self.tiles.get(x, y)
@@ -136,7 +174,7 @@ impl BaseMap for Map {
     }
 
     fn is_opaque(&self, pos: IVec2) -> bool {
-        self.tile(pos.x, pos.y) == &TileType::Wall
+        self.tile(pos.x, pos.y).is_opaque()
     }
 
     fn successors(&self, pos: IVec2) -> SmallVec<[(IVec2, f32); 8]> {
@@ -1,12 +1,11 @@
 use macroquad::prelude::IVec2;
 use smallvec::SmallVec;
-use std::mem::take;
 use rand::{thread_rng, Rng, rngs::ThreadRng};
 use crate::alg::AStarPath;
 use super::*;
 
 #[derive(PartialEq, Eq)]
-enum Stage { Partition, TrimRooms, Corridors, Done }
+enum Stage { Init, Partition, TrimRooms, Corridors, Done }
 
 #[derive(Default, Clone, Copy)]
 struct Node {
@@ -16,8 +15,6 @@ struct Node {
 }
 
 pub struct BSPGen {
-    tiles: Grid<TileType>,
-    depth: i32,
     rng: ThreadRng,
     stage: Stage,
     pf_cache: AStarPath,
@@ -27,52 +24,53 @@ pub struct BSPGen {
     idx: usize,
 }
 
+impl Default for BSPGen {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl BSPGen {
-    pub fn new(width: i32, height: i32, depth: i32) -> Self {
+    pub fn new() -> Self {
         Self {
-            tiles: Grid::new(width, height, TileType::Floor),
-            depth,
             rng: thread_rng(),
-            stage: Stage::Partition,
+            stage: Stage::Init,
             pf_cache: AStarPath::new(),
 
-            bsp_tree: vec![Node { rect: IRect::new(0, 0, width, height), ..Default::default() }],
+            bsp_tree: vec![],
             idx: 0,
             first_room: 0,
         }
     }
 
-    fn create_walls(&mut self, r: &IRect) {
-        for x in r.x..=r.xx {
-            *self.tiles.get_mut(x, r.y) = TileType::Wall;
-            *self.tiles.get_mut(x, r.yy) = TileType::Wall;
-        }
-
-        for y in r.y..=r.yy {
-            *self.tiles.get_mut(r.x, y) = TileType::Wall;
-            *self.tiles.get_mut(r.xx, y) = TileType::Wall;
+    fn init(&mut self, tiles: &mut Grid<TileType>) {
+        let (width, height) = (tiles.width(), tiles.height());
+        for t in tiles.iter_mut() {
+            *t = TileType::Floor;
         }
+        self.bsp_tree = vec![Node { rect: IRect::new(0, 0, width, height), ..Default::default() }];
+        self.stage = Stage::Partition;
     }
 
-    fn connect_rooms(&mut self, idx1: usize, idx2: usize) -> (i32, i32) {
+    fn connect_rooms(&mut self, tiles: &mut Grid<TileType>, idx1: usize, idx2: usize) -> (i32, i32) {
         let (fx, fy) = self.bsp_tree[idx1].cp;
         let (tx, ty) = self.bsp_tree[idx2].cp;
         let (from, to) = (IVec2::new(fx, fy), IVec2::new(tx, ty));
-        let bounds = IRect::new(1, 1, self.tiles.width() - 1, self.tiles.height() - 1);
+        let bounds = IRect::new(1, 1, tiles.width() - 1, tiles.height() - 1);
 
         let mut successors = |n: IVec2| -> SmallVec<[(IVec2, f32); 8]>{
             [(1, 0), (0, 1), (-1, 0), (0, -1)]
                 .into_iter()
                 .map(|(dx, dy)| (n.x + dx, n.y + dy))
                 .filter(|(x, y)| bounds.contains(*x, *y))
-                .map(|(x, y)| (IVec2::new(x, y), if self.tiles.get(x, y) == &TileType::Floor { 1. } else { 5. }))
+                .map(|(x, y)| (IVec2::new(x, y), if tiles.get(x, y) == &TileType::Floor { 1. } else { 5. }))
                 .collect()
         };
         let mut heuristic = |a: IVec2, b: IVec2| (b.x - a.x + b.y - a.y).abs() as f32;
         self.pf_cache.compute_generic(from, to, &mut heuristic, &mut successors);
 
         for (n, _) in self.pf_cache.result() {
-            *self.tiles.get_mut(n.x, n.y) = TileType::Floor;
+            *tiles.get_mut(n.x, n.y) = TileType::Floor;
         }
         let path = self.pf_cache.result();
         if !path.is_empty() {
@@ -83,14 +81,14 @@ impl BSPGen {
         }
     }
 
-    fn partition(&mut self) {
+    fn partition(&mut self, tiles: &mut Grid<TileType>) {
         if let Some((r1, r2)) = split(self.bsp_tree[self.idx].rect, &mut self.rng) {
             let n = self.bsp_tree.len();
             self.bsp_tree[self.idx].children = Some((n, n + 1));
             self.bsp_tree.push(Node { rect: r1, ..Default::default() });
             self.bsp_tree.push(Node { rect: r2, ..Default::default() });
-            self.create_walls(&r1);
-            self.create_walls(&r2);
+            create_walls(tiles, &r1);
+            create_walls(tiles, &r2);
             self.idx += 1;
         } else {
             self.first_room = self.idx;
@@ -98,11 +96,11 @@ impl BSPGen {
         }
     }
 
-    fn trim_rooms(&mut self) {
+    fn trim_rooms(&mut self, tiles: &mut Grid<TileType>) {
         let mut rect = self.bsp_tree[self.idx].rect;
         for y in rect.y..=rect.yy {
             for x in rect.x..=rect.xx {
-                *self.tiles.get_mut(x, y) = TileType::Wall;
+                *tiles.get_mut(x, y) = TileType::Wall;
             }
         }
 
@@ -112,7 +110,7 @@ impl BSPGen {
 
         for y in rect.y + 1..rect.yy {
             for x in rect.x + 1..rect.xx {
-                *self.tiles.get_mut(x, y) = TileType::Floor;
+                *tiles.get_mut(x, y) = TileType::Floor;
             }
         }
 
@@ -123,14 +121,16 @@ impl BSPGen {
         }
     }
 
-    fn corridors(&mut self) {
+    fn corridors(&mut self, data: &mut BuildData) {
         let (first, second) = self.bsp_tree[self.idx].children.unwrap();
-        self.bsp_tree[self.idx].cp = self.connect_rooms(first, second);
+        self.bsp_tree[self.idx].cp = self.connect_rooms(&mut data.tiles, first, second);
         if self.idx == 0 {
-            // self.connect_rooms(0, self.split_queue.len() - 1);
-            let idx = (self.first_room + self.bsp_tree.len()) / 2;
-            let (x, y) = self.bsp_tree[idx].cp;
-            *self.tiles.get_mut(x, y) = TileType::DownStairs;
+            let rooms: Vec<_> = self.bsp_tree[self.first_room..].iter()
+                .map(|n| n.rect)
+                .collect();
+            let (x, y) = rooms[0].center();
+            data.plp = Some(IVec2::new(x, y));
+            data.rooms = Some(rooms);
             self.stage = Stage::Done;
         } else {
             self.idx -= 1;
@@ -139,57 +139,32 @@ impl BSPGen {
 }
 
 impl MapBuilder for BSPGen {
-    fn progress(&mut self) -> bool {
+    fn progress(&mut self, data: &mut BuildData) -> bool {
         match self.stage {
-            Stage::Partition => self.partition(),
-            Stage::TrimRooms => self.trim_rooms(),
-            Stage::Corridors => self.corridors(),
+            Stage::Init => self.init(&mut data.tiles),
+            Stage::Partition => self.partition(&mut data.tiles),
+            Stage::TrimRooms => self.trim_rooms(&mut data.tiles),
+            Stage::Corridors => self.corridors(data),
             Stage::Done => (),
         };
         self.stage == Stage::Done
     }
+}
 
-    fn spawn(&self, ecs: &mut World, spawner: &mut Spawner) {
-        const MAX_DEPTH1_SPAWNS: i32 = 4;
-        spawner.set_depth(self.depth);
-
-        let mut rng = thread_rng();
-        let num_spawns = rng.gen_range(1..=MAX_DEPTH1_SPAWNS + self.depth);
-        let mut spawn_points = Vec::with_capacity(num_spawns as usize);
-
-        for n in &self.bsp_tree[self.first_room + 1..] {
-            let room = n.rect;
-            for _ in 1..=num_spawns {
-                loop {
-                    let x = rng.gen_range(room.x + 1..room.xx);
-                    let y = rng.gen_range(room.y + 1..room.yy);
-                    if !spawn_points.contains(&(x, y)) { 
-                        spawn_points.push((x, y));
-                        break;
-                    }
-                }
-            }
-        }
-
-        for (x, y) in spawn_points {
-            spawner.spawn(ecs, x, y);
-        }
-    }
+const MIN_SIZE: i32 = 7;
 
-    fn player_pos(&self) -> IVec2 { 
-        let (x, y) = self.bsp_tree[self.first_room].rect.center();
-        IVec2::new(x, y)
+fn create_walls(tiles: &mut Grid<TileType>, r: &IRect) {
+    for x in r.x..=r.xx {
+        *tiles.get_mut(x, r.y) = TileType::Wall;
+        *tiles.get_mut(x, r.yy) = TileType::Wall;
     }
 
-    fn intermediate(&self) -> IntermediateMap {
-        IntermediateMap { tiles: &self.tiles }
+    for y in r.y..=r.yy {
+        *tiles.get_mut(r.x, y) = TileType::Wall;
+        *tiles.get_mut(r.xx, y) = TileType::Wall;
     }
-
-    fn build(&mut self) -> Map { Map::from_grid(take(&mut self.tiles), self.depth) }
 }
 
-const MIN_SIZE: i32 = 7;
-
 fn split<R: Rng>(r: IRect, rng: &mut R) -> Option<(IRect, IRect)> {
     let roll = rng.gen_bool(sigmoid(r.width() as f64 / r.height() as f64 - 1.));
     let (w, h) = ((r.width() - 1) / 2, (r.height() - 1) / 2);
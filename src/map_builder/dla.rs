@@ -0,0 +1,63 @@
+use rand::{thread_rng, Rng};
+use super::*;
+
+///Diffusion-limited aggregation: particles wander in from random spots 
+///and stick to the first floor tile they bump into
+pub struct DiffusionLimitedAggregation {
+    started: bool,
+}
+
+impl Default for DiffusionLimitedAggregation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DiffusionLimitedAggregation {
+    pub fn new() -> Self {
+        Self { started: false }
+    }
+}
+
+impl MapBuilder for DiffusionLimitedAggregation {
+    fn progress(&mut self, data: &mut BuildData) -> bool {
+        const PARTICLES_PER_STEP: i32 = 60;
+        const TARGET_RATIO: f32 = 0.3;
+
+        let tiles = &mut data.tiles;
+        let (w, h) = (tiles.width(), tiles.height());
+        let inner = IRect::new(1, 1, w - 2, h - 2);
+        let mut rng = thread_rng();
+
+        if !self.started {
+            for t in tiles.iter_mut() {
+                *t = TileType::Wall;
+            }
+            let (cx, cy) = inner.center();
+            for (x, y) in IRect::new(cx - 1, cy - 1, 3, 3).iter() {
+                *tiles.get_mut(x, y) = TileType::Floor;
+            }
+            data.plp = Some(IVec2::new(cx, cy));
+            self.started = true;
+            return false;
+        }
+
+        for _ in 0..PARTICLES_PER_STEP {
+            let (mut x, mut y) = (rng.gen_range(inner.x..=inner.xx), rng.gen_range(inner.y..=inner.yy));
+            let (mut px, mut py) = (x, y);
+            while tiles.get(x, y) == &TileType::Wall {
+                px = x;
+                py = y;
+                let (dx, dy) = [(1, 0), (-1, 0), (0, 1), (0, -1)][rng.gen_range(0..4)];
+                if inner.contains(x + dx, y + dy) {
+                    x += dx;
+                    y += dy;
+                }
+            }
+            *tiles.get_mut(px, py) = TileType::Floor;
+        }
+
+        let floor = tiles.iter().filter(|t| *t == &TileType::Floor).count();
+        floor as f32 / (w * h) as f32 >= TARGET_RATIO
+    }
+}
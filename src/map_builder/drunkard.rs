@@ -0,0 +1,69 @@
+use rand::{thread_rng, Rng, seq::SliceRandom};
+use super::*;
+
+///Caves dug out by random walkers until enough of the map is floor
+pub struct DrunkardsWalk {
+    started: bool,
+}
+
+impl Default for DrunkardsWalk {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DrunkardsWalk {
+    pub fn new() -> Self {
+        Self { started: false }
+    }
+
+    fn floor_ratio(tiles: &Grid<TileType>) -> f32 {
+        let cnt = tiles.iter().filter(|t| *t == &TileType::Floor).count();
+        cnt as f32 / (tiles.width() * tiles.height()) as f32
+    }
+}
+
+impl MapBuilder for DrunkardsWalk {
+    fn progress(&mut self, data: &mut BuildData) -> bool {
+        const DIGGERS_PER_STEP: i32 = 2;
+        const LIFETIME: i32 = 300;
+        const TARGET_RATIO: f32 = 0.45;
+
+        let tiles = &mut data.tiles;
+        let (w, h) = (tiles.width(), tiles.height());
+        let inner = IRect::new(1, 1, w - 2, h - 2);
+        let mut rng = thread_rng();
+
+        if !self.started {
+            for t in tiles.iter_mut() {
+                *t = TileType::Wall;
+            }
+            let (x, y) = inner.center();
+            *tiles.get_mut(x, y) = TileType::Floor;
+            data.plp = Some(IVec2::new(x, y));
+            data.walk_ends = Some(vec![]);
+            self.started = true;
+            return false;
+        }
+
+        let floor: Vec<_> = inner.iter()
+            .filter(|(x, y)| tiles.get(*x, *y) == &TileType::Floor)
+            .collect();
+        for _ in 0..DIGGERS_PER_STEP {
+            let (mut x, mut y) = *floor.choose(&mut rng).unwrap();
+            for _ in 0..LIFETIME {
+                *tiles.get_mut(x, y) = TileType::Floor;
+                let (dx, dy) = [(1, 0), (-1, 0), (0, 1), (0, -1)][rng.gen_range(0..4)];
+                if inner.contains(x + dx, y + dy) {
+                    x += dx;
+                    y += dy;
+                }
+            }
+            if let Some(ends) = data.walk_ends.as_mut() {
+                ends.push(IVec2::new(x, y));
+            }
+        }
+
+        Self::floor_ratio(tiles) >= TARGET_RATIO
+    }
+}
@@ -0,0 +1,321 @@
+use std::fmt::Write;
+use macroquad::prelude::{Image, Color, ImageFormat};
+use super::*;
+use crate::{
+    comp::{Position, Renderable, Named, Player},
+    draw_map::{ViewMap, tile_glyph},
+    spawner::SpawnKind,
+    util::{Glyph, to_cp437, colors::YELLOW},
+};
+
+///Largest width or height a level file can have
+const MAX_SIZE: i32 = 1000;
+
+///A level in a human-readable form:
+///```text
+///depth 1
+///start 1 1
+///tiles 80 43
+///#####...
+///#<..M#..
+///spawns
+///12 7 Goblin
+///```
+///The optional `start` line gives the start, hand-written levels can instead mark it
+///with an `@` on a floor tile. `M`, `!`, `^` and `N` spawn a random monster, item, trap 
+///or NPC on a floor tile, the `spawns` section lists exact entities by name,
+///any name the `Spawner` doesn't know is an error.
+pub struct MapFile {
+    pub depth: i32,
+    pub tiles: Grid<TileType>,
+    pub start: Option<IVec2>,
+    pub kinds: Vec<(i32, i32, SpawnKind)>,
+    pub spawns: Vec<(i32, i32, String)>,
+}
+
+impl MapFile {
+    ///Captures the current level with everything lying around on it
+    pub fn from_world(ecs: &World) -> Self {
+        let map = ecs.fetch::<Map>();
+        let bounds = map.bounds();
+        let mut tiles = Grid::new(bounds.width(), bounds.height(), TileType::Wall);
+        for (x, y) in bounds.iter() {
+            *tiles.get_mut(x, y) = *map.tile(x, y);
+        }
+
+        let positions = ecs.read_storage::<Position>();
+        let names = ecs.read_storage::<Named>();
+        let players = ecs.read_storage::<Player>();
+        let mut start = None;
+        let mut spawns = vec![];
+        for (pos, name, player) in (&positions, &names, players.maybe()).join() {
+            match player {
+                Some(_) => start = Some(IVec2::new(pos.x, pos.y)),
+                None if Spawner::can_spawn_named(&name.0) => spawns.push((pos.x, pos.y, name.0.clone())),
+                //Gas clouds and such can't be saved to a file
+                None => (),
+            }
+        }
+        if start.is_none() {
+            start = ecs.try_fetch::<IVec2>().map(|p| *p);
+        }
+
+        Self { depth: map.depth(), tiles, start, kinds: vec![], spawns }
+    }
+
+    pub fn to_ascii(&self) -> String {
+        let mut s = String::new();
+        writeln!(s, "depth {}", self.depth).unwrap();
+        if let Some(start) = self.start {
+            writeln!(s, "start {} {}", start.x, start.y).unwrap();
+        }
+        writeln!(s, "tiles {} {}", self.tiles.width(), self.tiles.height()).unwrap();
+        for y in 0..self.tiles.height() {
+            for x in 0..self.tiles.width() {
+                let ch = if let Some((_, _, kind)) = self.kinds.iter().find(|k| k.0 == x && k.1 == y) {
+                    match kind {
+                        SpawnKind::Monster => 'M',
+                        SpawnKind::Item => '!',
+                        SpawnKind::Trap => '^',
+                        SpawnKind::Npc => 'N',
+                    }
+                } else {
+                    match self.tiles.get(x, y) {
+                        TileType::Wall => '#',
+                        TileType::Floor => '.',
+                        TileType::DownStairs => '>',
+                        TileType::UpStairs => '<',
+                        TileType::Door => '+',
+                    }
+                };
+                s.push(ch);
+            }
+            s.push('\n');
+        }
+        s.push_str("spawns\n");
+        for (x, y, name) in self.spawns.iter() {
+            writeln!(s, "{} {} {}", x, y, name).unwrap();
+        }
+        s
+    }
+
+    pub fn from_ascii(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().enumerate()
+            .map(|(i, l)| (i + 1, l.trim_end()))
+            .filter(|(_, l)| !l.is_empty());
+        let mut next = |what: &str| lines.next()
+            .ok_or_else(|| format!("unexpected end of file, expected {}", what));
+
+        let (n, line) = next("depth")?;
+        let depth = line.strip_prefix("depth ")
+            .and_then(|d| d.trim().parse().ok())
+            .ok_or_else(|| format!("line {}: expected `depth <n>`", n))?;
+
+        let (mut n, mut line) = next("tiles")?;
+        let mut start = None;
+        if let Some(s) = line.strip_prefix("start ") {
+            let p = s.split_once(' ')
+                .and_then(|(x, y)| Some(IVec2::new(x.trim().parse().ok()?, y.trim().parse().ok()?)))
+                .ok_or_else(|| format!("line {}: expected `start <x> <y>`", n))?;
+            start = Some((n, p));
+            (n, line) = next("tiles")?;
+        }
+        let (w, h): (i32, i32) = line.strip_prefix("tiles ")
+            .and_then(|s| s.split_once(' '))
+            .and_then(|(w, h)| Some((w.trim().parse().ok()?, h.trim().parse().ok()?)))
+            .ok_or_else(|| format!("line {}: expected `tiles <width> <height>`", n))?;
+        if !(1..=MAX_SIZE).contains(&w) || !(1..=MAX_SIZE).contains(&h) {
+            return Err(format!("line {}: width and height have to be between 1 and {}", n, MAX_SIZE));
+        }
+
+        let mut file = Self { 
+            depth, 
+            tiles: Grid::new(w, h, TileType::Wall), 
+            start: start.map(|(_, p)| p), 
+            kinds: vec![], 
+            spawns: vec![],
+        };
+        for y in 0..h {
+            let (n, row) = next("a row of tiles")?;
+            if row.chars().count() as i32 != w {
+                return Err(format!("line {}: expected {} tiles", n, w));
+            }
+            for (x, ch) in row.chars().enumerate() {
+                let x = x as i32;
+                *file.tiles.get_mut(x, y) = match ch {
+                    '#' => TileType::Wall,
+                    '>' => TileType::DownStairs,
+                    '<' => TileType::UpStairs,
+                    '+' => TileType::Door,
+                    '.' => TileType::Floor,
+                    '@' => { file.start = Some(IVec2::new(x, y)); TileType::Floor },
+                    'M' => { file.kinds.push((x, y, SpawnKind::Monster)); TileType::Floor },
+                    '!' => { file.kinds.push((x, y, SpawnKind::Item)); TileType::Floor },
+                    '^' => { file.kinds.push((x, y, SpawnKind::Trap)); TileType::Floor },
+                    'N' => { file.kinds.push((x, y, SpawnKind::Npc)); TileType::Floor },
+                    _ => return Err(format!("line {}: unknown tile `{}`", n, ch)),
+                };
+            }
+        }
+
+        let check = |tiles: &Grid<TileType>, n: usize, p: IVec2, what: &str| {
+            if !(0..w).contains(&p.x) || !(0..h).contains(&p.y) {
+                Err(format!("line {}: {} is outside the map", n, what))
+            } else if !tiles.get(p.x, p.y).is_walkable() {
+                Err(format!("line {}: {} isn't on a walkable tile", n, what))
+            } else {
+                Ok(())
+            }
+        };
+        if let Some((n, p)) = start {
+            check(&file.tiles, n, p, "the start")?;
+        }
+
+        if let Ok((n, line)) = next("spawns") {
+            if line != "spawns" {
+                return Err(format!("line {}: expected `spawns`", n));
+            }
+        }
+        for (n, line) in lines {
+            let mut parts = line.splitn(3, ' ');
+            let spawn = (|| Some((
+                parts.next()?.parse().ok()?, 
+                parts.next()?.parse().ok()?, 
+                parts.next()?.to_owned(),
+            )))().ok_or_else(|| format!("line {}: expected `<x> <y> <name>`", n))?;
+            check(&file.tiles, n, IVec2::new(spawn.0, spawn.1), &format!("`{}`", spawn.2))?;
+            if !Spawner::can_spawn_named(&spawn.2) {
+                return Err(format!("line {}: don't know how to spawn `{}`", n, spawn.2));
+            }
+            file.spawns.push(spawn);
+        }
+        Ok(file)
+    }
+
+    pub fn save(&self, path: &str) {
+        std::fs::write(path, self.to_ascii())
+            .unwrap_or_else(|e| panic!("failed to write {}: {}", path, e));
+    }
+
+    pub fn load(path: &str) -> Self {
+        let text = std::fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("failed to read {}: {}", path, e));
+        Self::from_ascii(&text)
+            .unwrap_or_else(|e| panic!("failed to parse {}: {}", path, e))
+    }
+}
+
+///Draws the whole level, revealed or not, and everything on it with the CP437 `atlas`
+pub fn render_png(ecs: &World, atlas: &Image) -> Image {
+    let file = MapFile::from_world(ecs);
+    let map = IntermediateMap { tiles: &file.tiles };
+    let (gw, gh) = (atlas.width() / 16, atlas.height() / 16);
+    let (w, h) = (file.tiles.width(), file.tiles.height());
+    let mut image = Image::gen_image_color((w as usize * gw) as u16, (h as usize * gh) as u16, 
+        Color::new(0., 0., 0., 1.));
+
+    let mut cells = Grid::new(w, h, (0, [0.; 4]));
+    for (x, y) in map.bounds().iter() {
+        *cells.get_mut(x, y) = tile_glyph(&map, x, y);
+    }
+    if let Some(start) = file.start {
+        *cells.get_mut(start.x, start.y) = (to_cp437('@'), YELLOW);
+    }
+    let positions = ecs.read_storage::<Position>();
+    let renderables = ecs.read_storage::<Renderable>();
+    let mut drawables: Vec<_> = (&positions, &renderables).join().collect();
+    drawables.sort_unstable_by_key(|(_, r)| -r.order);
+    for (pos, r) in drawables {
+        *cells.get_mut(pos.x, pos.y) = (r.glyph, r.fg);
+    }
+
+    for (x, y) in map.bounds().iter() {
+        let (glyph, fg) = *cells.get(x, y);
+        blit_glyph(&mut image, atlas, glyph, fg, x as usize * gw, y as usize * gh);
+    }
+    image
+}
+
+fn blit_glyph(image: &mut Image, atlas: &Image, glyph: Glyph, fg: [f32; 4], x: usize, y: usize) {
+    let (gw, gh) = (atlas.width() / 16, atlas.height() / 16);
+    let (ax, ay) = (glyph as usize % 16 * gw, glyph as usize / 16 * gh);
+    for dy in 0..gh {
+        for dx in 0..gw {
+            let src = atlas.get_pixel((ax + dx) as u32, (ay + dy) as u32);
+            let a = src.a * fg[3];
+            if a <= 0. { continue; }
+            let dst = image.get_pixel((x + dx) as u32, (y + dy) as u32);
+            let blend = |d: f32, s: f32, f: f32| d * (1. - a) + s * f * a;
+            image.set_pixel((x + dx) as u32, (y + dy) as u32, Color::new(
+                blend(dst.r, src.r, fg[0]),
+                blend(dst.g, src.g, fg[1]),
+                blend(dst.b, src.b, fg[2]),
+                1.,
+            ));
+        }
+    }
+}
+
+pub fn load_atlas_image(path: &str) -> Image {
+    let bytes = std::fs::read(path)
+        .unwrap_or_else(|e| panic!("failed to read {}: {}", path, e));
+    Image::from_file_with_format(&bytes, Some(ImageFormat::Png))
+}
+
+///Writes `<name>.txt` and `<name>.png` for the current level
+pub fn export_level(ecs: &World, name: &str) {
+    MapFile::from_world(ecs).save(&format!("{}.txt", name));
+    render_png(ecs, &load_atlas_image("atlas.png")).export_png(&format!("{}.png", name));
+}
+
+///Stamps a `MapFile` onto the map a few rows at a time
+pub struct MapFileBuilder {
+    file: MapFile,
+    row: i32,
+}
+
+impl MapFileBuilder {
+    pub fn new(file: MapFile) -> Self {
+        Self { file, row: 0 }
+    }
+
+    pub fn load(path: &str) -> Self {
+        Self::new(MapFile::load(path))
+    }
+}
+
+impl MapBuilder for MapFileBuilder {
+    fn progress(&mut self, data: &mut BuildData) -> bool {
+        const ROWS_PER_STEP: i32 = 4;
+        let (w, h) = (data.tiles.width(), data.tiles.height());
+        if self.row == 0 {
+            for t in data.tiles.iter_mut() {
+                *t = TileType::Wall;
+            }
+            data.plp = self.file.start;
+        }
+
+        let rows = self.row..(self.row + ROWS_PER_STEP).min(self.file.tiles.height()).min(h);
+        for y in rows {
+            for x in 0..self.file.tiles.width().min(w) {
+                *data.tiles.get_mut(x, y) = *self.file.tiles.get(x, y);
+            }
+        }
+        self.row += ROWS_PER_STEP;
+        self.row >= self.file.tiles.height().min(h)
+    }
+
+    fn spawn(&self, data: &BuildData, ecs: &mut World, spawner: &mut Spawner) {
+        let bounds = IRect::new(0, 0, data.tiles.width(), data.tiles.height());
+        for (x, y, kind) in self.file.kinds.iter() {
+            if bounds.contains(*x, *y) {
+                spawner.spawn_kind(ecs, *x, *y, *kind);
+            }
+        }
+        for (x, y, name) in self.file.spawns.iter() {
+            if bounds.contains(*x, *y) {
+                spawner.spawn_named(ecs, *x, *y, name);
+            }
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use rand::{thread_rng, seq::SliceRandom};
+use super::*;
+
+///Recursive backtracker maze with one tile wide corridors.
+///Cells live on odd coordinates, the tiles between them are walls or passages.
+pub struct MazeBuilder {
+    stack: Vec<(i32, i32)>,
+    visited: Grid<bool>,
+    started: bool,
+}
+
+impl Default for MazeBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MazeBuilder {
+    pub fn new() -> Self {
+        Self {
+            stack: vec![],
+            visited: Grid::default(),
+            started: false,
+        }
+    }
+}
+
+impl MapBuilder for MazeBuilder {
+    fn progress(&mut self, data: &mut BuildData) -> bool {
+        const CELLS_PER_STEP: i32 = 40;
+        let tiles = &mut data.tiles;
+        let (cols, rows) = ((tiles.width() - 1) / 2, (tiles.height() - 1) / 2);
+        let mut rng = thread_rng();
+
+        if !self.started {
+            for t in tiles.iter_mut() {
+                *t = TileType::Wall;
+            }
+            self.visited = Grid::new(cols, rows, false);
+            *self.visited.get_mut(0, 0) = true;
+            *tiles.get_mut(1, 1) = TileType::Floor;
+            self.stack.push((0, 0));
+            data.plp = Some(IVec2::new(1, 1));
+            self.started = true;
+            return false;
+        }
+
+        let cells = IRect::new(0, 0, cols, rows);
+        for _ in 0..CELLS_PER_STEP {
+            let (cx, cy) = match self.stack.last() {
+                Some(c) => *c,
+                None => return true,
+            };
+
+            let mut next = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+            next.shuffle(&mut rng);
+            let next = next.iter()
+                .map(|(dx, dy)| (cx + dx, cy + dy))
+                .find(|(x, y)| cells.contains(*x, *y) && !*self.visited.get(*x, *y));
+
+            match next {
+                Some((nx, ny)) => {
+                    *self.visited.get_mut(nx, ny) = true;
+                    *tiles.get_mut(2 * nx + 1, 2 * ny + 1) = TileType::Floor;
+                    *tiles.get_mut(cx + nx + 1, cy + ny + 1) = TileType::Floor;
+                    self.stack.push((nx, ny));
+                },
+                None => { self.stack.pop(); },
+            }
+        }
+
+        self.stack.is_empty()
+    }
+}
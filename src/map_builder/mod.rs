@@ -1,37 +1,281 @@
+use std::rc::Rc;
+use std::mem::take;
 use macroquad::prelude::IVec2;
-use specs::World;
+use specs::prelude::*;
 use crate::{
     map::*, 
     spawner::Spawner, 
-    util::{Grid, IRect},
+    util::{Grid, IRect, adjacent},
+    alg::BFS,
+    random_table::RandomTable,
 };
 
 mod simple;
 mod bsp;
 mod cellular;
+mod prefab;
+mod drunkard;
+mod maze;
+mod voronoi;
+mod dla;
+mod modifiers;
+mod spawning;
+mod validator;
+mod map_file;
+mod town;
+mod stats;
 
 pub use simple::*;
 pub use bsp::*;
 pub use cellular::*;
+pub use prefab::*;
+pub use drunkard::*;
+pub use maze::*;
+pub use voronoi::*;
+pub use dla::*;
+pub use modifiers::*;
+pub use spawning::*;
+pub use validator::*;
+pub use map_file::*;
+pub use town::*;
+pub use stats::*;
 
 const FLAGS: TileFlags = TileFlags {
     visible: true,
     revealed: true,
     blocked: false,
     bloodstained: false,
+    known_trap: false,
 };
 
 pub struct IntermediateMap<'a> {
     tiles: &'a Grid<TileType>,
 }
 
+///Everything the steps of a chain share and pass along
+pub struct BuildData {
+    pub tiles: Grid<TileType>,
+    pub depth: i32,
+    pub plp: Option<IVec2>,
+    ///Room rects including their walls, if the generator has rooms at all
+    pub rooms: Option<Vec<IRect>>,
+    ///Areas that spawning steps have to leave alone, e.g. prefabs
+    pub reserved: Vec<IRect>,
+    ///Region id of every tile, if the generator splits the map into regions
+    pub regions: Option<Grid<i32>>,
+    ///Where each random walker stopped digging, if the generator digs with walkers
+    pub walk_ends: Option<Vec<IVec2>>,
+}
+
+impl BuildData {
+    pub fn is_reserved(&self, x: i32, y: i32) -> bool {
+        self.reserved.iter().any(|r| r.contains(x, y))
+    }
+}
+
+///A single step of a `BuilderChain`: either a base generator or a modifier
 pub trait MapBuilder {
-    fn progress(&mut self) -> bool;
-    fn spawn(&self, ecs: &mut World, spawner: &mut Spawner);
+    ///Does a bit of work, returns true once the step is finished
+    fn progress(&mut self, data: &mut BuildData) -> bool;
+
+    fn spawn(&self, _data: &BuildData, _ecs: &mut World, _spawner: &mut Spawner) {}
+}
+
+pub struct BuilderChain {
+    data: BuildData,
+    steps: Vec<Box<dyn MapBuilder>>,
+    current: usize,
+}
+
+impl BuilderChain {
+    pub fn new<B: MapBuilder + 'static>(width: i32, height: i32, depth: i32, base: B) -> Self {
+        Self {
+            data: BuildData {
+                tiles: Grid::new(width, height, TileType::Wall),
+                depth,
+                plp: None,
+                rooms: None,
+                reserved: vec![],
+                regions: None,
+                walk_ends: None,
+            },
+            steps: vec![Box::new(base)],
+            current: 0,
+        }
+    }
+
+    pub fn with<B: MapBuilder + 'static>(mut self, step: B) -> Self {
+        self.steps.push(Box::new(step));
+        self
+    }
+
+    pub fn progress(&mut self) -> bool {
+        if let Some(step) = self.steps.get_mut(self.current) {
+            if step.progress(&mut self.data) {
+                self.current += 1;
+            }
+        }
+        self.current >= self.steps.len()
+    }
+
+    pub fn spawn(&self, ecs: &mut World, spawner: &mut Spawner) {
+        spawner.set_depth(self.data.depth);
+        for step in self.steps.iter() {
+            step.spawn(&self.data, ecs, spawner);
+        }
+    }
+
+    pub fn player_pos(&self) -> IVec2 {
+        self.data.plp.expect("builder chain didn't pick a starting point")
+    }
+
+    pub fn validate_layout(&self) -> Vec<MapProblem> {
+        validate_layout(&self.data)
+    }
+
+    pub fn data(&self) -> &BuildData {
+        &self.data
+    }
+
+    pub fn intermediate(&self) -> IntermediateMap<'_> {
+        IntermediateMap { tiles: &self.data.tiles }
+    }
+
+    pub fn build(&mut self) -> Map { 
+        Map::from_grid(take(&mut self.data.tiles), self.data.depth) 
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Recipe { 
+    Caves, MirroredCaves, Rooms, ErodedRooms, SimpleRooms, 
+    DrunkardCaves, Maze, Hive, Aggregation,
+}
+
+impl Recipe {
+    pub const ALL: [Recipe; 9] = [
+        Recipe::Caves, Recipe::MirroredCaves, Recipe::Rooms, Recipe::ErodedRooms, 
+        Recipe::SimpleRooms, Recipe::DrunkardCaves, Recipe::Maze, Recipe::Hive, 
+        Recipe::Aggregation,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Recipe> {
+        Self::ALL.into_iter().find(|r| format!("{:?}", r).eq_ignore_ascii_case(name))
+    }
+}
+
+pub fn random_builder(width: i32, height: i32, depth: i32, prefabs: Rc<Vec<Prefab>>) -> BuilderChain {
+    use Recipe::*;
+    if depth == 0 {
+        return BuilderChain::new(width, height, depth, TownBuilder::new());
+    }
+
+    let mut table = RandomTable::new();
+    table.extend([
+        (Caves, 4), (Rooms, 4), (SimpleRooms, 2),
+        (MirroredCaves, depth.min(3)), (ErodedRooms, depth.min(3)),
+        (DrunkardCaves, 3), (Hive, 2), (Aggregation, 2), (Maze, (depth - 1).clamp(0, 2)),
+    ].into_iter());
+
+    with_way_up(recipe_builder(*table.roll(), width, height, depth, prefabs), depth)
+}
+
+///A recipe that passes validation at every depth, for when random ones keep failing
+pub fn fallback_builder(width: i32, height: i32, depth: i32, prefabs: Rc<Vec<Prefab>>) -> BuilderChain {
+    if depth == 0 {
+        return BuilderChain::new(width, height, depth, TownBuilder::new());
+    }
+    with_way_up(recipe_builder(Recipe::Hive, width, height, depth, prefabs), depth)
+}
+
+fn with_way_up(chain: BuilderChain, depth: i32) -> BuilderChain {
+    match depth {
+        //The way back up to town
+        1 => chain.with(UpStairsAtStart),
+        _ => chain,
+    }
+}
+
+pub fn recipe_builder(recipe: Recipe, width: i32, height: i32, depth: i32, 
+    prefabs: Rc<Vec<Prefab>>) -> BuilderChain 
+{
+    use Recipe::*;
+    match recipe {
+        Caves => BuilderChain::new(width, height, depth, CellularAutomata::new())
+            .with(Prefabs::new(prefabs))
+            .with(AddDoors)
+            .with(StairsAtFarthest)
+            .with(NoiseAreaSpawning),
+        MirroredCaves => BuilderChain::new(width, height, depth, CellularAutomata::new())
+            .with(Mirror)
+            .with(CullUnreachable)
+            .with(StairsAtFarthest)
+            .with(NoiseAreaSpawning),
+        Rooms => BuilderChain::new(width, height, depth, BSPGen::new())
+            .with(Prefabs::new(prefabs))
+            .with(AddDoors)
+            .with(StairsAtFarthest)
+            .with(RoomSpawning),
+        ErodedRooms => BuilderChain::new(width, height, depth, BSPGen::new())
+            .with(ErodeWalls::new(2))
+            .with(CullUnreachable)
+            .with(Prefabs::new(prefabs))
+            .with(StairsAtFarthest)
+            .with(NoiseAreaSpawning),
+        SimpleRooms => BuilderChain::new(width, height, depth, SimpleBuilder::new())
+            .with(AddDoors)
+            .with(StairsAtFarthest)
+            .with(RoomSpawning),
+        DrunkardCaves => BuilderChain::new(width, height, depth, DrunkardsWalk::new())
+            .with(Prefabs::new(prefabs))
+            .with(StairsAtFarthest)
+            .with(WalkEndSpawning),
+        Maze => BuilderChain::new(width, height, depth, MazeBuilder::new())
+            .with(StairsAtFarthest)
+            .with(DeadEndSpawning),
+        Hive => BuilderChain::new(width, height, depth, VoronoiHive::default())
+            .with(CullUnreachable)
+            .with(AddDoors)
+            .with(StairsAtFarthest)
+            .with(RegionSpawning),
+        Aggregation => BuilderChain::new(width, height, depth, DiffusionLimitedAggregation::new())
+            .with(StairsAtFarthest)
+            .with(DistanceSpawning),
+    }
+}
+
+///Walking distance from `from` to every tile, -1 if unreachable
+pub fn walk_distances(tiles: &Grid<TileType>, from: IVec2) -> Grid<i32> {
+    let bounds = IRect::new(0, 0, tiles.width(), tiles.height());
+    let mut g = Grid::new(tiles.width(), tiles.height(), -1);
+    let adjacent = |_: &mut Grid<i32>, (x, y): &(i32, i32)| 
+        adjacent(*x, *y)
+        .filter(|(x, y)| bounds.contains(*x, *y) && tiles.get(*x, *y).is_walkable())
+        .collect();
+
+    BFS::default().search([(from.x, from.y)], &mut g,
+        |g, (x, y), c| *g.get_mut(*x, *y) = c, 
+        |g, (x, y)| *g.get(*x, *y), 
+        adjacent,
+    );
+    g
+}
+
+///The floor tile closest to `from`, ignoring walls in between
+pub fn nearest_floor(tiles: &Grid<TileType>, from: IVec2) -> Option<IVec2> {
+    let bounds = IRect::new(0, 0, tiles.width(), tiles.height());
+    let mut g = Grid::new(tiles.width(), tiles.height(), -1);
+    let adjacent = |_: &mut Grid<i32>, (x, y): &(i32, i32)| 
+        adjacent(*x, *y)
+        .filter(|(x, y)| bounds.contains(*x, *y))
+        .collect();
 
-    fn player_pos(&self) -> IVec2;
-    fn intermediate(&self) -> IntermediateMap;
-    fn build(&mut self) -> Map;
+    BFS::default().search_until([(from.x, from.y)], &mut g,
+        |g, (x, y), c| *g.get_mut(*x, *y) = c, 
+        |g, (x, y)| *g.get(*x, *y), 
+        adjacent,
+        |_, (x, y)| tiles.get(*x, *y) == &TileType::Floor,
+    ).map(|(x, y)| IVec2::new(x, y))
 }
 
 impl<'a> ViewMap for IntermediateMap<'a> {
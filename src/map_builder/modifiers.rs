@@ -0,0 +1,171 @@
+use rand::{thread_rng, Rng};
+use super::*;
+
+///Turns every walkable tile that can't be reached from `from` into a wall
+pub fn cull_unreachable(tiles: &mut Grid<TileType>, from: IVec2) {
+    let dist = walk_distances(tiles, from);
+    for (tile, d) in tiles.iter_mut().zip(dist.iter()) {
+        if *d < 0 && tile.is_walkable() {
+            *tile = TileType::Wall;
+        }
+    }
+}
+
+pub struct CullUnreachable;
+
+impl MapBuilder for CullUnreachable {
+    fn progress(&mut self, data: &mut BuildData) -> bool {
+        let plp = data.plp.expect("nothing to cull from");
+        cull_unreachable(&mut data.tiles, plp);
+        true
+    }
+}
+
+///Puts the down stairs onto the floor tile furthest away from the start
+pub struct StairsAtFarthest;
+
+impl MapBuilder for StairsAtFarthest {
+    fn progress(&mut self, data: &mut BuildData) -> bool {
+        let plp = data.plp.expect("no starting point to measure from");
+        let dist = walk_distances(&data.tiles, plp);
+        let bounds = IRect::new(0, 0, data.tiles.width(), data.tiles.height());
+
+        let exit = bounds.iter()
+            .filter(|(x, y)| data.tiles.get(*x, *y) == &TileType::Floor 
+                && !data.is_reserved(*x, *y))
+            .max_by_key(|(x, y)| *dist.get(*x, *y));
+        if let Some((x, y)) = exit {
+            *data.tiles.get_mut(x, y) = TileType::DownStairs;
+        }
+        true
+    }
+}
+
+///Puts up stairs where the player starts
+pub struct UpStairsAtStart;
+
+impl MapBuilder for UpStairsAtStart {
+    fn progress(&mut self, data: &mut BuildData) -> bool {
+        let plp = data.plp.expect("no starting point for the stairs");
+        *data.tiles.get_mut(plp.x, plp.y) = TileType::UpStairs;
+        true
+    }
+}
+
+///Places doors where corridors enter rooms, or in narrow cave passages 
+///if there are no rooms
+pub struct AddDoors;
+
+impl AddDoors {
+    fn is_doorway(tiles: &Grid<TileType>, x: i32, y: i32) -> bool {
+        let wall = |x, y| tiles.get(x, y) == &TileType::Wall;
+        let floor = |x, y| tiles.get(x, y) == &TileType::Floor;
+        floor(x, y) && (
+            wall(x - 1, y) && wall(x + 1, y) && floor(x, y - 1) && floor(x, y + 1) ||
+            wall(x, y - 1) && wall(x, y + 1) && floor(x - 1, y) && floor(x + 1, y)
+        )
+    }
+}
+
+impl MapBuilder for AddDoors {
+    fn progress(&mut self, data: &mut BuildData) -> bool {
+        const CAVE_DOOR_CHANCE: f64 = 0.1;
+        let (w, h) = (data.tiles.width(), data.tiles.height());
+        let inner = IRect::new(1, 1, w - 2, h - 2);
+        let mut rng = thread_rng();
+
+        let candidates: Vec<_> = match &data.rooms {
+            Some(rooms) => rooms.iter()
+                .flat_map(|r| r.iter().filter(move |(x, y)| 
+                    *x == r.x || *x == r.xx || *y == r.y || *y == r.yy))
+                .filter(|(x, y)| inner.contains(*x, *y))
+                .collect(),
+            None => inner.iter()
+                .filter(|_| rng.gen_bool(CAVE_DOOR_CHANCE))
+                .collect(),
+        };
+
+        for (x, y) in candidates {
+            if data.is_reserved(x, y) { continue; }
+            let next_to_door = adjacent(x, y)
+                .any(|(x, y)| data.tiles.get(x, y) == &TileType::Door);
+            if !next_to_door && Self::is_doorway(&data.tiles, x, y) {
+                *data.tiles.get_mut(x, y) = TileType::Door;
+            }
+        }
+        true
+    }
+}
+
+///Knocks down wall tiles that are mostly surrounded by floor
+pub struct ErodeWalls {
+    iterations: i32,
+}
+
+impl ErodeWalls {
+    pub fn new(iterations: i32) -> Self {
+        Self { iterations }
+    }
+}
+
+impl MapBuilder for ErodeWalls {
+    fn progress(&mut self, data: &mut BuildData) -> bool {
+        let (w, h) = (data.tiles.width(), data.tiles.height());
+        let before = data.tiles.clone();
+        let mut rng = thread_rng();
+
+        for (x, y) in IRect::new(1, 1, w - 2, h - 2).iter() {
+            if before.get(x, y) != &TileType::Wall || data.is_reserved(x, y) { continue; }
+            let floors = adjacent(x, y)
+                .filter(|(x, y)| before.get(*x, *y) == &TileType::Floor)
+                .count();
+            if floors >= 4 && rng.gen_bool(0.5) {
+                *data.tiles.get_mut(x, y) = TileType::Floor;
+            }
+        }
+
+        self.iterations -= 1;
+        self.iterations <= 0
+    }
+}
+
+///Copies the left half of the map onto the right one
+pub struct Mirror;
+
+impl MapBuilder for Mirror {
+    fn progress(&mut self, data: &mut BuildData) -> bool {
+        let (w, h) = (data.tiles.width(), data.tiles.height());
+        for y in 0..h {
+            for x in 0..w / 2 {
+                *data.tiles.get_mut(w - 1 - x, y) = *data.tiles.get(x, y);
+            }
+        }
+
+        if let Some(rooms) = data.rooms.as_mut() {
+            rooms.retain(|r| r.xx < w / 2);
+            let mirrored: Vec<_> = rooms.iter()
+                .map(|r| IRect { x: w - 1 - r.xx, xx: w - 1 - r.x, ..*r })
+                .collect();
+            rooms.extend(mirrored);
+        }
+        data.reserved.retain(|r| r.xx < w / 2);
+        if let Some(regions) = data.regions.as_mut() {
+            for y in 0..h {
+                for x in 0..w / 2 {
+                    *regions.get_mut(w - 1 - x, y) = *regions.get(x, y);
+                }
+            }
+        }
+
+        if let Some(ends) = data.walk_ends.as_mut() {
+            ends.retain(|p| p.x < w / 2);
+            let mirrored: Vec<_> = ends.iter().map(|p| IVec2::new(w - 1 - p.x, p.y)).collect();
+            ends.extend(mirrored);
+        }
+
+        if let Some(plp) = data.plp {
+            data.plp = nearest_floor(&data.tiles, plp);
+        }
+        true
+    }
+}
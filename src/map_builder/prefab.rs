@@ -0,0 +1,138 @@
+use std::rc::Rc;
+use rand::{thread_rng, seq::SliceRandom};
+use serde::Deserialize;
+use super::*;
+use crate::spawner::SpawnKind;
+
+
+///A hand-authored room loaded from `prefabs.json`.
+///
+///Legend: `#` wall, `.` floor, `+` door, `M` monster, `!` item, `^` trap,
+///a space leaves the underlying tile untouched.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Prefab {
+    pub name: String,
+    pub template: Vec<String>,
+    #[serde(default)]
+    pub min_depth: i32,
+    #[serde(default = "default_max_depth")]
+    pub max_depth: i32,
+    #[serde(default = "default_weight")]
+    pub weight: i32,
+}
+
+fn default_max_depth() -> i32 { i32::MAX }
+fn default_weight() -> i32 { 1 }
+
+impl Prefab {
+    pub fn width(&self) -> i32 {
+        self.template.first().map(|r| r.chars().count() as i32).unwrap_or(0)
+    }
+
+    pub fn height(&self) -> i32 {
+        self.template.len() as i32
+    }
+
+    fn cells(&self) -> impl Iterator<Item = (i32, i32, char)> + '_ {
+        self.template.iter().enumerate().flat_map(|(y, row)| 
+            row.chars().enumerate().map(move |(x, ch)| (x as i32, y as i32, ch)))
+    }
+}
+
+pub fn load_prefabs(path: &str) -> Vec<Prefab> {
+    let data = std::fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("failed to read {}: {}", path, e));
+    let prefabs: Vec<Prefab> = serde_json::from_str(&data)
+        .unwrap_or_else(|e| panic!("failed to parse {}: {}", path, e));
+
+    for p in prefabs.iter() {
+        assert!(p.template.iter().all(|r| r.chars().count() as i32 == p.width()),
+            "prefab {} has rows of different length", p.name);
+    }
+    prefabs
+}
+
+///Stamps a few prefabs into the map, one per `progress()` call
+pub struct Prefabs {
+    library: Rc<Vec<Prefab>>,
+    remaining: i32,
+    spawns: Vec<(i32, i32, SpawnKind)>,
+}
+
+impl Prefabs {
+    pub fn new(library: Rc<Vec<Prefab>>) -> Self {
+        const MAX_PREFABS: i32 = 2;
+        Self {
+            library,
+            remaining: MAX_PREFABS,
+            spawns: vec![],
+        }
+    }
+
+    fn place_one(&mut self, data: &mut BuildData) {
+        let mut table = RandomTable::new();
+        table.extend(self.library.iter().enumerate()
+            .filter(|(_, p)| (p.min_depth..=p.max_depth).contains(&data.depth))
+            .map(|(i, p)| (i, p.weight)));
+        if table.is_empty() { 
+            self.remaining = 0;
+            return; 
+        }
+
+        let prefab = &self.library[*table.roll()];
+        let plp = data.plp.unwrap_or_default();
+        let tiles = &data.tiles;
+        let (w, h) = (prefab.width(), prefab.height());
+
+        //The footprint plus a one tile margin has to be open floor,
+        //so the prefab can't cut the level in two
+        let fits = |x: i32, y: i32| {
+            let margin = IRect::new(x - 1, y - 1, w + 2, h + 2);
+            margin.x >= 1 && margin.y >= 1 
+                && margin.xx < tiles.width() - 1 && margin.yy < tiles.height() - 1
+                && !margin.contains(plp.x, plp.y)
+                && !data.reserved.iter().any(|r| r.overlaps(&margin))
+                && margin.iter().all(|(x, y)| tiles.get(x, y) == &TileType::Floor)
+        };
+        let spots: Vec<_> = IRect::new(0, 0, tiles.width(), tiles.height())
+            .iter()
+            .filter(|(x, y)| fits(*x, *y))
+            .collect();
+
+        if let Some((x0, y0)) = spots.choose(&mut thread_rng()).cloned() {
+            for (dx, dy, ch) in prefab.cells() {
+                let (x, y) = (x0 + dx, y0 + dy);
+                let tile = data.tiles.get_mut(x, y);
+                match ch {
+                    '#' => *tile = TileType::Wall,
+                    '.' => *tile = TileType::Floor,
+                    '+' => *tile = TileType::Door,
+                    'M' => self.spawns.push((x, y, SpawnKind::Monster)),
+                    '!' => self.spawns.push((x, y, SpawnKind::Item)),
+                    '^' => self.spawns.push((x, y, SpawnKind::Trap)),
+                    _ => (),
+                };
+            }
+            data.reserved.push(IRect::new(x0, y0, w, h));
+        }
+        self.remaining -= 1;
+    }
+}
+
+impl MapBuilder for Prefabs {
+    fn progress(&mut self, data: &mut BuildData) -> bool {
+        if self.remaining > 0 {
+            self.place_one(data);
+        }
+        self.remaining <= 0
+    }
+
+    fn spawn(&self, data: &BuildData, ecs: &mut World, spawner: &mut Spawner) {
+        for (x, y, kind) in self.spawns.iter() {
+            let area = data.reserved.iter().find(|r| r.contains(*x, *y)).cloned();
+            spawner.set_area(area);
+            spawner.spawn_kind(ecs, *x, *y, *kind);
+        }
+        spawner.set_area(None);
+    }
+}
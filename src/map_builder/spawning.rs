@@ -0,0 +1,277 @@
+use std::{collections::HashMap, iter::repeat_n};
+use rand::{thread_rng, Rng, seq::SliceRandom};
+use simdnoise::{CellDistanceFunction, NoiseBuilder, CellReturnType};
+use super::*;
+use crate::spawner::SpawnKind;
+
+///Most spawns in one room or area on the first level, one more for every level deeper
+pub const MAX_DEPTH1_SPAWNS: i32 = 4;
+const NUM_WAYPOINTS: usize = 8;
+
+fn floor_tiles(data: &BuildData) -> Vec<(i32, i32)> {
+    IRect::new(0, 0, data.tiles.width(), data.tiles.height()).iter()
+        .filter(|(x, y)| data.tiles.get(*x, *y) == &TileType::Floor)
+        .collect()
+}
+
+fn random_waypoints(data: &BuildData) -> Vec<(i32, i32)> {
+    floor_tiles(data)
+        .choose_multiple(&mut thread_rng(), NUM_WAYPOINTS)
+        .cloned()
+        .collect()
+}
+
+fn can_spawn_at(data: &BuildData, x: i32, y: i32) -> bool {
+    data.tiles.get(x, y) == &TileType::Floor && !data.is_reserved(x, y)
+        && data.plp != Some(IVec2::new(x, y))
+}
+
+///Spawns a few things in every room but the starting one
+pub struct RoomSpawning;
+
+impl MapBuilder for RoomSpawning {
+    fn progress(&mut self, _: &mut BuildData) -> bool { true }
+
+    fn spawn(&self, data: &BuildData, ecs: &mut World, spawner: &mut Spawner) {
+        let rooms = data.rooms.as_ref().expect("room spawning needs rooms");
+        let plp = data.plp.unwrap_or_default();
+
+        let mut rng = thread_rng();
+        let num_spawns = rng.gen_range(1..=MAX_DEPTH1_SPAWNS + data.depth) as usize;
+
+        spawner.set_waypoints(rooms.iter().map(|r| r.center()).collect());
+
+        for room in rooms.iter() {
+            if room.contains(plp.x, plp.y) { continue; }
+            let inner = IRect { x: room.x + 1, y: room.y + 1, xx: room.xx - 1, yy: room.yy - 1 };
+            let spawn_points: Vec<_> = inner.iter()
+                .filter(|(x, y)| can_spawn_at(data, *x, *y))
+                .collect();
+
+            spawner.set_area(Some(inner));
+            for (x, y) in spawn_points.choose_multiple(&mut rng, num_spawns) {
+                spawner.spawn(ecs, *x, *y);
+            }
+        }
+        spawner.set_area(None);
+    }
+}
+
+///Splits the map into cellular noise areas and spawns a few things in each
+pub struct NoiseAreaSpawning;
+
+impl NoiseAreaSpawning {
+    fn spawn_in(data: &BuildData, ecs: &mut World, spawner: &mut Spawner, area: &[usize]) {
+        let w = data.tiles.width();
+
+        let mut rng = thread_rng();
+        let max_spawns = (MAX_DEPTH1_SPAWNS + data.depth).min(area.len() as i32);
+
+        let mut bounds = IRect::new(area[0] as i32 % w, area[0] as i32 / w, 1, 1);
+        for i in area {
+            let (x, y) = (*i as i32 % w, *i as i32 / w);
+            bounds.x = bounds.x.min(x); bounds.xx = bounds.xx.max(x);
+            bounds.y = bounds.y.min(y); bounds.yy = bounds.yy.max(y);
+        }
+        spawner.set_area(Some(bounds));
+
+        let mut needed = rng.gen_range(1..=max_spawns) as u32;
+        let mut left = area.len() as u32;
+        for i in area {
+            if rng.gen_range(0.0..1.0) < needed as f32 / left as f32 {
+                let i = *i as i32;
+                let (x, y) = (i % w, i / w);
+                if can_spawn_at(data, x, y) {
+                    spawner.spawn(ecs, x, y);
+                    needed -= 1;
+                }
+            }
+            left -= 1;
+        }
+    }
+}
+
+impl MapBuilder for NoiseAreaSpawning {
+    fn progress(&mut self, _: &mut BuildData) -> bool { true }
+
+    fn spawn(&self, data: &BuildData, ecs: &mut World, spawner: &mut Spawner) {
+        let (w, h) = (data.tiles.width(), data.tiles.height());
+        spawner.set_waypoints(random_waypoints(data));
+
+        let (noise, _, _) = NoiseBuilder::cellular_2d(w as usize, h as usize)
+            .with_seed(1337)
+            .with_distance_function(CellDistanceFunction::Manhattan)
+            .with_return_type(CellReturnType::CellValue)
+            .with_freq(0.08)
+            .generate();
+        let mut indices = (0..(w * h) as usize).collect::<Vec<_>>();
+        indices.sort_unstable_by(|idx1, idx2| 
+            noise[*idx1].partial_cmp(&noise[*idx2]).unwrap());
+
+        let (mut start, mut start_idx) = (0, indices[0]);
+        for (i, idx) in indices.iter().skip(1).enumerate() {
+            if (noise[*idx] - noise[start_idx]).abs() > f32::EPSILON {
+                Self::spawn_in(data, ecs, spawner, &indices[start..i + 1]);
+                start = i;
+                start_idx = *idx;
+            }
+        }
+        Self::spawn_in(data, ecs, spawner, &indices[start..]);
+        spawner.set_area(None);
+    }
+}
+
+///Spawns a few things in every region but the starting one
+pub struct RegionSpawning;
+
+impl MapBuilder for RegionSpawning {
+    fn progress(&mut self, _: &mut BuildData) -> bool { true }
+
+    fn spawn(&self, data: &BuildData, ecs: &mut World, spawner: &mut Spawner) {
+        let regions = data.regions.as_ref().expect("region spawning needs regions");
+        let plp = data.plp.unwrap_or_default();
+        let start_region = *regions.get(plp.x, plp.y);
+
+        let mut areas: HashMap<i32, Vec<(i32, i32)>> = HashMap::new();
+        for (x, y) in floor_tiles(data) {
+            areas.entry(*regions.get(x, y)).or_default().push((x, y));
+        }
+        spawner.set_waypoints(areas.values()
+            .map(|a| a[a.len() / 2])
+            .collect());
+
+        let mut rng = thread_rng();
+        for (region, area) in areas.iter() {
+            if *region == start_region { continue; }
+
+            let mut bounds = IRect::new(area[0].0, area[0].1, 1, 1);
+            for (x, y) in area.iter() {
+                bounds.x = bounds.x.min(*x); bounds.xx = bounds.xx.max(*x);
+                bounds.y = bounds.y.min(*y); bounds.yy = bounds.yy.max(*y);
+            }
+            spawner.set_area(Some(bounds));
+
+            let max_spawns = (MAX_DEPTH1_SPAWNS + data.depth) as usize / 2;
+            let num_spawns = rng.gen_range(1..=max_spawns.max(1));
+            for (x, y) in area.choose_multiple(&mut rng, num_spawns) {
+                if can_spawn_at(data, *x, *y) {
+                    spawner.spawn(ecs, *x, *y);
+                }
+            }
+        }
+        spawner.set_area(None);
+    }
+}
+
+///Scatters spawns over the map, more of them the further from the start
+pub struct DistanceSpawning;
+
+impl MapBuilder for DistanceSpawning {
+    fn progress(&mut self, _: &mut BuildData) -> bool { true }
+
+    fn spawn(&self, data: &BuildData, ecs: &mut World, spawner: &mut Spawner) {
+        const SAFE_DISTANCE: i32 = 8;
+        const TILES_PER_SPAWN: i32 = 40;
+        let plp = data.plp.unwrap_or_default();
+        let dist = walk_distances(&data.tiles, plp);
+        let max_dist = dist.iter().copied().max().unwrap_or(0).max(1);
+
+        spawner.set_waypoints(random_waypoints(data));
+
+        let floor = floor_tiles(data);
+        let num_spawns = floor.len() as i32 / TILES_PER_SPAWN + data.depth;
+        let mut rng = thread_rng();
+        let mut spawned = 0;
+        for (x, y) in floor.choose_multiple(&mut rng, floor.len()) {
+            if spawned >= num_spawns { break; }
+            let d = *dist.get(*x, *y);
+            if d < SAFE_DISTANCE || !can_spawn_at(data, *x, *y) { continue; }
+            if rng.gen_bool(d as f64 / max_dist as f64) {
+                spawner.spawn(ecs, *x, *y);
+                spawned += 1;
+            }
+        }
+    }
+}
+
+///Hides items at dead ends, puts monsters and traps along the corridors
+pub struct DeadEndSpawning;
+
+impl MapBuilder for DeadEndSpawning {
+    fn progress(&mut self, _: &mut BuildData) -> bool { true }
+
+    fn spawn(&self, data: &BuildData, ecs: &mut World, spawner: &mut Spawner) {
+        const DEAD_END_ITEM_CHANCE: f64 = 0.3;
+        let floor = floor_tiles(data);
+        let exits = |x: i32, y: i32| [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]
+            .iter()
+            .filter(|(x, y)| data.tiles.get(*x, *y).is_walkable())
+            .count();
+
+        let junctions: Vec<_> = floor.iter()
+            .filter(|(x, y)| exits(*x, *y) >= 3)
+            .cloned()
+            .collect();
+        let mut rng = thread_rng();
+        spawner.set_waypoints(junctions
+            .choose_multiple(&mut rng, NUM_WAYPOINTS)
+            .cloned()
+            .collect());
+
+        for (x, y) in floor.iter() {
+            if exits(*x, *y) == 1 && can_spawn_at(data, *x, *y) 
+                && rng.gen_bool(DEAD_END_ITEM_CHANCE) 
+            {
+                spawner.spawn_kind(ecs, *x, *y, SpawnKind::Item);
+            }
+        }
+
+        let mut corridors: Vec<_> = floor.iter()
+            .filter(|(x, y)| exits(*x, *y) == 2 && can_spawn_at(data, *x, *y))
+            .collect();
+        corridors.shuffle(&mut rng);
+        let num_monsters = (MAX_DEPTH1_SPAWNS + data.depth) as usize;
+        let kinds = repeat_n(SpawnKind::Monster, num_monsters)
+            .chain(repeat_n(SpawnKind::Trap, data.depth as usize));
+        for ((x, y), kind) in corridors.into_iter().zip(kinds) {
+            spawner.spawn_kind(ecs, *x, *y, kind);
+        }
+    }
+}
+
+///Spawns a few things around the spots where the walkers of a drunkard's walk 
+///stopped digging, away from the start
+pub struct WalkEndSpawning;
+
+impl MapBuilder for WalkEndSpawning {
+    fn progress(&mut self, _: &mut BuildData) -> bool { true }
+
+    fn spawn(&self, data: &BuildData, ecs: &mut World, spawner: &mut Spawner) {
+        const SAFE_DISTANCE: i32 = 8;
+        const RADIUS: i32 = 3;
+        let ends = data.walk_ends.as_ref().expect("walk end spawning needs walk ends");
+        let plp = data.plp.unwrap_or_default();
+        let mut rng = thread_rng();
+        spawner.set_waypoints(ends
+            .choose_multiple(&mut rng, NUM_WAYPOINTS)
+            .map(|p| (p.x, p.y))
+            .collect());
+
+        let bounds = IRect::new(0, 0, data.tiles.width(), data.tiles.height());
+        let max_spawns = (MAX_DEPTH1_SPAWNS + data.depth) as usize / 2;
+        for end in ends.iter() {
+            if (*end - plp).abs().max_element() < SAFE_DISTANCE { continue; }
+            let area = IRect::new(end.x - RADIUS, end.y - RADIUS, 2 * RADIUS + 1, 2 * RADIUS + 1);
+            let spawn_points: Vec<_> = area.iter()
+                .filter(|(x, y)| bounds.contains(*x, *y) && can_spawn_at(data, *x, *y))
+                .collect();
+
+            spawner.set_area(Some(area));
+            let num_spawns = rng.gen_range(0..=max_spawns);
+            for (x, y) in spawn_points.choose_multiple(&mut rng, num_spawns) {
+                spawner.spawn(ecs, *x, *y);
+            }
+        }
+        spawner.set_area(None);
+    }
+}
@@ -0,0 +1,117 @@
+use std::{collections::BTreeMap, time::Instant};
+use specs::saveload::SimpleMarkerAllocator;
+use super::*;
+use crate::comp::{register_all_components, SerializeMe};
+use crate::sprites::Sprites;
+
+const MAX_STEPS: usize = 10_000;
+
+#[derive(Default)]
+struct RecipeStats {
+    runs: usize,
+    steps: usize,
+    floor: f32,
+    exit_distance: i32,
+    spawns: usize,
+    failures: BTreeMap<&'static str, usize>,
+}
+
+impl RecipeStats {
+    fn record(&mut self, chain: &BuilderChain, steps: usize, spawns: usize) {
+        let data = chain.data();
+        let floor = data.tiles.iter().filter(|t| t.is_walkable()).count();
+        self.runs += 1;
+        self.steps += steps;
+        self.floor += floor as f32 / (data.tiles.width() * data.tiles.height()) as f32;
+        self.spawns += spawns;
+
+        if let Some(plp) = data.plp {
+            let dist = walk_distances(&data.tiles, plp);
+            self.exit_distance += data.tiles.iter().zip(dist.iter())
+                .find(|(t, _)| **t == TileType::DownStairs)
+                .map_or(0, |(_, d)| (*d).max(0));
+        }
+
+        let problems = chain.validate_layout().into_iter()
+            .chain(validate_spawns(data, spawns));
+        for p in problems {
+            *self.failures.entry(p.name()).or_default() += 1;
+        }
+    }
+}
+
+///A world with nothing but the things `chain` spawns
+pub fn headless_world(chain: &BuilderChain) -> World {
+    let mut ecs = World::new();
+    register_all_components(&mut ecs);
+    ecs.insert(SimpleMarkerAllocator::<SerializeMe>::new());
+    ecs.insert(Sprites::default());
+    chain.spawn(&mut ecs, &mut Spawner::new(chain.data().depth));
+    ecs
+}
+
+fn count_spawns(chain: &BuilderChain) -> usize {
+    let ecs = headless_world(chain);
+    let cnt = ecs.entities().join().count();
+    cnt
+}
+
+///Generates a level from a recipe name or a map file and exports it 
+///as `<name>.txt` and `<name>.png`
+pub fn export_generated(width: i32, height: i32, source: &str, depth: i32, name: &str,
+    prefabs: Rc<Vec<Prefab>>)
+{
+    let mut chain = if source.ends_with(".txt") {
+        BuilderChain::new(width, height, depth, MapFileBuilder::load(source))
+    } else {
+        let recipe = Recipe::from_name(source)
+            .unwrap_or_else(|| panic!("unknown recipe {}, try one of {:?}", source, Recipe::ALL));
+        recipe_builder(recipe, width, height, depth, prefabs)
+    };
+    while !chain.progress() {}
+
+    let mut ecs = headless_world(&chain);
+    ecs.insert(chain.player_pos());
+    ecs.insert(chain.build());
+    export_level(&ecs, name);
+}
+
+///Generates `runs` maps of every recipe at every depth without opening a window
+///and prints averages and validation failures
+pub fn print_mapgen_stats(width: i32, height: i32, depths: &[i32], runs: usize, 
+    prefabs: Rc<Vec<Prefab>>) 
+{
+    println!("{:<14}{:>6}{:>6}{:>8}{:>8}{:>8}{:>8}{:>10}  failures",
+        "recipe", "depth", "runs", "steps", "floor", "exit", "spawns", "ms/map");
+    for recipe in Recipe::ALL {
+        for depth in depths.iter().copied() {
+            let mut stats = RecipeStats::default();
+            let start = Instant::now();
+            for _ in 0..runs {
+                let mut chain = recipe_builder(recipe, width, height, depth, prefabs.clone());
+                let mut steps = 1;
+                while !chain.progress() && steps < MAX_STEPS {
+                    steps += 1;
+                }
+                if steps >= MAX_STEPS {
+                    *stats.failures.entry("hangs").or_default() += 1;
+                    continue;
+                }
+                let spawns = count_spawns(&chain);
+                stats.record(&chain, steps, spawns);
+            }
+            let elapsed = start.elapsed().as_secs_f32() * 1000.;
+
+            let n = stats.runs.max(1) as f32;
+            let failures = stats.failures.iter()
+                .map(|(name, cnt)| format!("{} x{}", name, cnt))
+                .collect::<Vec<_>>()
+                .join(", ");
+            println!("{:<14}{:>6}{:>6}{:>8.1}{:>7.0}%{:>8.1}{:>8.1}{:>10.1}  {}",
+                format!("{:?}", recipe), depth, runs, 
+                stats.steps as f32 / n, stats.floor / n * 100., 
+                stats.exit_distance as f32 / n, stats.spawns as f32 / n,
+                elapsed / runs.max(1) as f32, failures);
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use super::*;
+
+const TOWN_FILE: &str = "levels/town.txt";
+
+///The surface level the game starts on, laid out in `levels/town.txt`
+pub struct TownBuilder {
+    file: MapFileBuilder,
+}
+
+impl Default for TownBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TownBuilder {
+    pub fn new() -> Self {
+        Self { file: MapFileBuilder::load(TOWN_FILE) }
+    }
+}
+
+impl MapBuilder for TownBuilder {
+    fn progress(&mut self, data: &mut BuildData) -> bool {
+        self.file.progress(data)
+    }
+
+    fn spawn(&self, data: &BuildData, ecs: &mut World, spawner: &mut Spawner) {
+        //Villagers are free to stroll around the whole town
+        let (w, h) = (data.tiles.width(), data.tiles.height());
+        spawner.set_area(Some(IRect::new(1, 1, w - 2, h - 2)));
+        self.file.spawn(data, ecs, spawner);
+        spawner.set_area(None);
+    }
+}
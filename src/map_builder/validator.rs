@@ -0,0 +1,83 @@
+use super::*;
+use super::spawning::MAX_DEPTH1_SPAWNS;
+
+///Walking distance the exit has to be away from the start at least
+pub const MIN_EXIT_DISTANCE: i32 = 15;
+///Bounds for the number of spawns per floor tile, the upper one on the first level
+pub const MIN_SPAWN_DENSITY: f32 = 0.005;
+pub const MAX_SPAWN_DENSITY: f32 = 0.1;
+
+///The upper spawn density bound at `depth`, growing like the spawns per area do
+pub fn max_spawn_density(depth: i32) -> f32 {
+    MAX_SPAWN_DENSITY * (MAX_DEPTH1_SPAWNS + depth.max(1)) as f32 / (MAX_DEPTH1_SPAWNS + 1) as f32
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MapProblem {
+    NoStart,
+    StartNotWalkable,
+    Disconnected { unreachable: usize },
+    NoStairs,
+    StairsUnreachable,
+    ExitTooClose { distance: i32 },
+    SpawnDensity { density: f32 },
+}
+
+impl MapProblem {
+    pub fn name(&self) -> &'static str {
+        use MapProblem::*;
+        match self {
+            NoStart => "no start",
+            StartNotWalkable => "start not walkable",
+            Disconnected { .. } => "disconnected",
+            NoStairs => "no stairs",
+            StairsUnreachable => "stairs unreachable",
+            ExitTooClose { .. } => "exit too close",
+            SpawnDensity { .. } => "spawn density",
+        }
+    }
+}
+
+///Checks that every walkable tile and the stairs can be reached from the start,
+///and that the stairs aren't right next to it
+pub fn validate_layout(data: &BuildData) -> Vec<MapProblem> {
+    let plp = match data.plp {
+        Some(plp) => plp,
+        None => return vec![MapProblem::NoStart],
+    };
+    if !data.tiles.get(plp.x, plp.y).is_walkable() {
+        return vec![MapProblem::StartNotWalkable];
+    }
+
+    let mut problems = vec![];
+    let dist = walk_distances(&data.tiles, plp);
+    let unreachable = data.tiles.iter().zip(dist.iter())
+        .filter(|(t, d)| t.is_walkable() && **d < 0)
+        .count();
+    if unreachable > 0 {
+        problems.push(MapProblem::Disconnected { unreachable });
+    }
+
+    let bounds = IRect::new(0, 0, data.tiles.width(), data.tiles.height());
+    match bounds.iter().find(|(x, y)| data.tiles.get(*x, *y) == &TileType::DownStairs) {
+        None => problems.push(MapProblem::NoStairs),
+        Some((x, y)) => match *dist.get(x, y) {
+            d if d < 0 => problems.push(MapProblem::StairsUnreachable),
+            d if d < MIN_EXIT_DISTANCE => 
+                problems.push(MapProblem::ExitTooClose { distance: d }),
+            _ => (),
+        },
+    }
+    problems
+}
+
+///Checks that the map is neither empty nor crammed with stuff
+pub fn validate_spawns(data: &BuildData, num_spawns: usize) -> Option<MapProblem> {
+    let floor = data.tiles.iter().filter(|t| t.is_walkable()).count().max(1);
+    let density = num_spawns as f32 / floor as f32;
+    if (MIN_SPAWN_DENSITY..=max_spawn_density(data.depth)).contains(&density) {
+        None
+    } else {
+        Some(MapProblem::SpawnDensity { density })
+    }
+}
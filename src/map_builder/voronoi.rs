@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use rand::{thread_rng, Rng, seq::SliceRandom};
+use super::*;
+
+#[derive(Eq, PartialEq)]
+enum Stage {
+    Seeds,
+    Regions,
+    Walls,
+    Connect(usize),
+}
+
+///Splits the map into Voronoi cells walled off from each other,
+///then punches a hole between every pair of neighbouring cells
+pub struct VoronoiHive {
+    stage: Stage,
+    num_seeds: usize,
+    seeds: Vec<(i32, i32)>,
+    ///Every pair of neighbouring regions and the walls separating them
+    borders: Vec<Vec<(i32, i32)>>,
+}
+
+impl Default for VoronoiHive {
+    fn default() -> Self {
+        Self::new(32)
+    }
+}
+
+impl VoronoiHive {
+    pub fn new(num_seeds: usize) -> Self {
+        Self {
+            stage: Stage::Seeds,
+            num_seeds,
+            seeds: vec![],
+            borders: vec![],
+        }
+    }
+
+    fn seeds(&mut self, data: &mut BuildData) {
+        let (w, h) = (data.tiles.width(), data.tiles.height());
+        let mut rng = thread_rng();
+        while self.seeds.len() < self.num_seeds {
+            let seed = (rng.gen_range(1..w - 1), rng.gen_range(1..h - 1));
+            if !self.seeds.contains(&seed) {
+                self.seeds.push(seed);
+            }
+        }
+        for t in data.tiles.iter_mut() {
+            *t = TileType::Wall;
+        }
+        self.stage = Stage::Regions;
+    }
+
+    fn regions(&mut self, data: &mut BuildData) {
+        let (w, h) = (data.tiles.width(), data.tiles.height());
+        let mut regions = Grid::new(w, h, 0);
+        for (x, y) in IRect::new(0, 0, w, h).iter() {
+            let nearest = self.seeds.iter()
+                .enumerate()
+                .min_by_key(|(_, (sx, sy))| (sx - x).pow(2) + (sy - y).pow(2))
+                .unwrap().0;
+            *regions.get_mut(x, y) = nearest as i32;
+        }
+
+        for (x, y) in IRect::new(1, 1, w - 2, h - 2).iter() {
+            *data.tiles.get_mut(x, y) = TileType::Floor;
+        }
+        data.regions = Some(regions);
+        self.stage = Stage::Walls;
+    }
+
+    fn walls(&mut self, data: &mut BuildData) {
+        let (w, h) = (data.tiles.width(), data.tiles.height());
+        let regions = data.regions.as_ref().unwrap();
+        let inner = IRect::new(1, 1, w - 2, h - 2);
+
+        for (x, y) in inner.iter() {
+            let r = *regions.get(x, y);
+            if *regions.get(x + 1, y) != r || *regions.get(x, y + 1) != r {
+                *data.tiles.get_mut(x, y) = TileType::Wall;
+            }
+        }
+
+        let floor = |x, y| inner.contains(x, y) && data.tiles.get(x, y) == &TileType::Floor;
+        let mut borders: HashMap<(i32, i32), Vec<(i32, i32)>> = HashMap::new();
+        for (x, y) in inner.iter() {
+            if floor(x, y) { continue; }
+            for ((ax, ay), (bx, by)) in [((x - 1, y), (x + 1, y)), ((x, y - 1), (x, y + 1))] {
+                if !floor(ax, ay) || !floor(bx, by) { continue; }
+                let (ra, rb) = (*regions.get(ax, ay), *regions.get(bx, by));
+                if ra != rb {
+                    borders.entry((ra.min(rb), ra.max(rb))).or_default().push((x, y));
+                }
+            }
+        }
+        self.borders = borders.into_values().collect();
+
+        let plp = self.seeds.iter()
+            .map(|(x, y)| IVec2::new(*x, *y))
+            .find_map(|p| nearest_floor(&data.tiles, p));
+        data.plp = plp;
+        self.stage = Stage::Connect(0);
+    }
+
+    fn connect(&mut self, data: &mut BuildData, i: usize) -> bool {
+        const BORDERS_PER_STEP: usize = 4;
+        let mut rng = thread_rng();
+        for border in self.borders.iter().skip(i).take(BORDERS_PER_STEP) {
+            let (x, y) = *border.choose(&mut rng).unwrap();
+            *data.tiles.get_mut(x, y) = TileType::Floor;
+        }
+
+        let next = i + BORDERS_PER_STEP;
+        self.stage = Stage::Connect(next);
+        next >= self.borders.len()
+    }
+}
+
+impl MapBuilder for VoronoiHive {
+    fn progress(&mut self, data: &mut BuildData) -> bool {
+        match self.stage {
+            Stage::Seeds => self.seeds(data),
+            Stage::Regions => self.regions(data),
+            Stage::Walls => self.walls(data),
+            Stage::Connect(i) => return self.connect(data, i),
+        }
+        false
+    }
+}
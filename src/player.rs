@@ -1,24 +1,34 @@
 use std::io::Write;
 use macroquad::prelude::*;
 use specs::prelude::*;
+use ::rand::{thread_rng, seq::SliceRandom};
 use crate::{
     comp::*, 
-    util::GameLog, 
+    util::{GameLog, NoiseMap, WALK_NOISE, SNEAK_NOISE}, 
     map::{Map, TileType, ViewMap}, 
+    camera::Camera,
+    travel::{self, Travel},
     state::RunState,
     gui::UIState,
+    map_builder,
+    inventory,
+    sprites::Sprites,
 };
 
 pub fn try_move_player(dx: i32, dy: i32, ecs: &mut World) -> RunState {
     let mut positions = ecs.write_storage::<Position>();
     let mut viewsheds = ecs.write_storage::<Viewshed>();
     let mut entity_moved = ecs.write_storage::<EntityMoved>();
+    let mut sneaking = ecs.write_storage::<Sneaking>();
+    let mut encumbered = ecs.write_storage::<Encumbered>();
     let players = ecs.read_storage::<Player>();
     let map = ecs.fetch::<Map>();
     let entities = ecs.entities();
 
     let (entity, _, pos, viewshed) = (&entities, &players, &mut positions, &mut viewsheds).join().next().unwrap();
 
+    let stumbled = stumble(ecs, entity);
+    let (dx, dy) = stumbled.unwrap_or((dx, dy));
     let (dst_x, dst_y) = (pos.x + dx, pos.y + dy);
 
     if !map.tile_flags(dst_x, dst_y).blocked {
@@ -27,9 +37,23 @@ pub fn try_move_player(dx: i32, dy: i32, ecs: &mut World) -> RunState {
         viewshed.dirty = true;
         entity_moved.insert(entity, EntityMoved {}).expect("failed to insert entity moved");
         *ecs.write_resource::<IVec2>() = IVec2::new(dst_x, dst_y);
+
+        let volume = match sneaking.get_mut(entity) {
+            Some(sneaking) => {
+                sneaking.lagging = true;
+                SNEAK_NOISE
+            },
+            None => WALK_NOISE,
+        };
+        if let Some(encumbered) = encumbered.get_mut(entity) {
+            encumbered.lagging = true;
+        }
+        ecs.write_resource::<NoiseMap>().emit(dst_x, dst_y, volume);
         RunState::PlayerTurn
     } else {
         let combat_stats = ecs.read_storage::<CombatStats>();
+        let npcs = ecs.read_storage::<Npc>();
+        let names = ecs.read_storage::<Named>();
         let mut wants_to_melee = ecs.write_storage::<WantsToMelee>();
 
         for potential_target in map.tile_content(dst_x, dst_y) {
@@ -37,12 +61,37 @@ pub fn try_move_player(dx: i32, dy: i32, ecs: &mut World) -> RunState {
                 wants_to_melee.insert(entity, WantsToMelee { target: *potential_target }).unwrap();
                 return RunState::PlayerTurn;
             }
+            if ecs.read_storage::<Vendor>().contains(*potential_target) {
+                return RunState::UI(UIState::Trade { vendor: *potential_target, selling: false, page: 0 });
+            }
+            if npcs.contains(*potential_target) {
+                let name = names.get(*potential_target).map(|n| n.0.as_str()).unwrap_or("Someone");
+                write!(ecs.fetch_mut::<GameLog>().new_entry(), "{} greets you.", name).unwrap();
+                return RunState::PlayerTurn;
+            }
         }
 
-        RunState::AwaitingInput
+        match stumbled {
+            Some(_) => RunState::PlayerTurn,
+            None => RunState::AwaitingInput,
+        }
     }
 }
 
+///A confused player moves in a random direction instead, wearing the confusion off
+fn stumble(ecs: &World, player: Entity) -> Option<(i32, i32)> {
+    let mut confused = ecs.write_storage::<Confusion>();
+    let confusion = confused.get_mut(player)?;
+    confusion.turns -= 1;
+    if confusion.turns <= 0 {
+        confused.remove(player);
+    }
+    write!(ecs.fetch_mut::<GameLog>().new_entry(), "You stumble around in confusion.").unwrap();
+    [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)]
+        .choose(&mut thread_rng())
+        .copied()
+}
+
 
 pub fn transform_movement_input(key: KeyCode) -> Option<(i32, i32)> {
     match key {
@@ -58,23 +107,71 @@ pub fn transform_movement_input(key: KeyCode) -> Option<(i32, i32)> {
     }
 }
 
-pub fn handle_input(ecs: &mut World) -> RunState {
+///Left clicking a tile next to the player attacks a monster there, 
+///any other tile is travelled to
+fn click(ecs: &mut World, mouse: (i32, i32)) -> RunState {
+    let camera = *ecs.fetch::<Camera>();
+    if !camera.viewport.contains(mouse.0, mouse.1) {
+        return RunState::AwaitingInput;
+    }
+    let (x, y) = camera.to_map(mouse.0, mouse.1);
     let plp = *ecs.fetch::<IVec2>();
-    if let Some(key) = get_last_key_pressed() {
+    let d = IVec2::new(x, y) - plp;
+    if d.abs().max_element() == 1 {
+        let attack = {
+            let monsters = ecs.read_storage::<Monster>();
+            ecs.fetch::<Map>().tile_content(x, y).iter().any(|e| monsters.contains(*e))
+        };
+        if attack {
+            return try_move_player(d.x, d.y, ecs);
+        }
+    }
+    if d == IVec2::ZERO {
+        return RunState::AwaitingInput;
+    }
+    travel::travel_to(ecs, IVec2::new(x, y))
+}
+
+///`mouse` is the console cell under the mouse
+pub fn handle_input(ecs: &mut World, mouse: (i32, i32)) -> RunState {
+    let plp = *ecs.fetch::<IVec2>();
+    let key = get_last_key_pressed();
+    if key.is_some() || is_mouse_button_pressed(MouseButton::Left) {
+        ecs.fetch_mut::<Travel>().cancel();
+    }
+    if is_mouse_button_pressed(MouseButton::Left) {
+        return click(ecs, mouse);
+    }
+    if ecs.fetch::<Travel>().is_active() {
+        return travel::step(ecs);
+    }
+    if let Some(key) = key {
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
         if let Some((dx, dy)) = transform_movement_input(key) {
             return try_move_player(dx, dy, ecs);
         }
         match key {
             //Inventory, items
-            KeyCode::I => RunState::UI(UIState::UseItem),
-            KeyCode::D => RunState::UI(UIState::DropItem),
+            KeyCode::I => RunState::UI(UIState::UseItem(0)),
+            KeyCode::D => RunState::UI(UIState::DropItem(0)),
             KeyCode::G => get_item(ecs),
+            KeyCode::C => RunState::UI(UIState::CastSpell),
 
             //Misc
             KeyCode::X => RunState::UI(UIState::Examine(plp)),
+            KeyCode::M => RunState::UI(UIState::MessageLog(0)),
             KeyCode::Space => RunState::PlayerTurn,
             KeyCode::Escape => RunState::SaveGame,
+            KeyCode::Period if shift => travel::travel_to_stairs(ecs, TileType::DownStairs),
+            KeyCode::Comma if shift => travel::travel_to_stairs(ecs, TileType::UpStairs),
             KeyCode::Period => try_go_deeper(ecs, plp),
+            KeyCode::Comma => try_go_up(ecs, plp),
+            KeyCode::O => travel::explore(ecs),
+            KeyCode::S => toggle_sneaking(ecs),
+            KeyCode::F => search(ecs),
+            KeyCode::T => disarm(ecs, plp),
+            KeyCode::F2 => toggle_sprites(ecs),
+            KeyCode::F12 => export_level(ecs),
             _ => RunState::AwaitingInput,
         }
     } else {
@@ -99,6 +196,10 @@ fn get_item(ecs: &mut World) -> RunState {
     }
 
     if let Some(item) = target_item {
+        if !inventory::can_carry(ecs, *player_entity, item) {
+            write!(log.new_entry(), "You can't carry any more.").unwrap();
+            return RunState::AwaitingInput;
+        }
         ecs.write_storage::<WantsToPickupItem>()
             .insert(*player_entity, WantsToPickupItem { item })
             .expect("unable to insert WantToPickupItem");
@@ -119,3 +220,107 @@ fn try_go_deeper(ecs: &World, plp: IVec2) -> RunState {
         RunState::AwaitingInput
     }
 }
+
+fn try_go_up(ecs: &World, plp: IVec2) -> RunState {
+    if let TileType::UpStairs = ecs.fetch::<Map>().tile(plp.x, plp.y) {
+        RunState::PrevLevel
+    } else {
+        write!(ecs.fetch_mut::<GameLog>().new_entry(),
+            "There is no way up from here.").unwrap();
+        RunState::AwaitingInput
+    }
+}
+
+fn toggle_sneaking(ecs: &mut World) -> RunState {
+    let player_entity = *ecs.fetch::<Entity>();
+    let mut sneaking = ecs.write_storage::<Sneaking>();
+    let mut log = ecs.fetch_mut::<GameLog>();
+
+    if sneaking.remove(player_entity).is_some() {
+        write!(log.new_entry(), "You stop sneaking.").unwrap();
+    } else {
+        sneaking.insert(player_entity, Sneaking { lagging: false })
+            .expect("failed to insert Sneaking");
+        write!(log.new_entry(), "You start sneaking, moving slowly but quietly.").unwrap();
+    }
+    RunState::AwaitingInput
+}
+
+fn toggle_sprites(ecs: &mut World) -> RunState {
+    let mut sprites = ecs.fetch_mut::<Sprites>();
+    let mut log = ecs.fetch_mut::<GameLog>();
+
+    if sprites.sheet.is_none() {
+        write!(log.new_entry(), "No sprite sheet loaded.").unwrap();
+    } else {
+        sprites.enabled = !sprites.enabled;
+        match sprites.enabled {
+            true => write!(log.new_entry(), "Switched to tiles.").unwrap(),
+            false => write!(log.new_entry(), "Switched to ASCII.").unwrap(),
+        }
+    }
+    RunState::AwaitingInput
+}
+
+fn search(ecs: &mut World) -> RunState {
+    let player_entity = *ecs.fetch::<Entity>();
+    ecs.write_storage::<WantsToSearch>().insert(player_entity, WantsToSearch {})
+        .expect("failed to insert WantsToSearch");
+    if let Some(viewshed) = ecs.write_storage::<Viewshed>().get_mut(player_entity) {
+        viewshed.dirty = true;
+    }
+    write!(ecs.fetch_mut::<GameLog>().new_entry(), "You search your surroundings.").unwrap();
+    RunState::PlayerTurn
+}
+
+///Tries to disarm a revealed trap next to the player
+fn disarm(ecs: &mut World, plp: IVec2) -> RunState {
+    let player_entity = *ecs.fetch::<Entity>();
+    let trap = {
+        let entities = ecs.entities();
+        let positions = ecs.read_storage::<Position>();
+        let triggers = ecs.read_storage::<EntryTrigger>();
+        let hidden = ecs.read_storage::<Hidden>();
+        (&entities, &positions, &triggers, !&hidden).join()
+            .find(|(_, pos, _, _)| (pos.x - plp.x).abs() <= 1 && (pos.y - plp.y).abs() <= 1)
+            .map(|(e, _, _, _)| e)
+    };
+
+    match trap {
+        Some(trap) => {
+            ecs.write_storage::<WantsToDisarm>().insert(player_entity, WantsToDisarm { trap })
+                .expect("failed to insert WantsToDisarm");
+            RunState::PlayerTurn
+        },
+        None => {
+            write!(ecs.fetch_mut::<GameLog>().new_entry(), "There is no trap nearby to disarm.").unwrap();
+            RunState::AwaitingInput
+        },
+    }
+}
+
+///Sneaking or encumbered steps take twice as long, so monsters act once more after them
+pub fn movement_lag(ecs: &mut World) -> bool {
+    let player_entity = *ecs.fetch::<Entity>();
+    if let Some(sneaking) = ecs.write_storage::<Sneaking>().get_mut(player_entity) {
+        if sneaking.lagging {
+            sneaking.lagging = false;
+            return true;
+        }
+    }
+    match ecs.write_storage::<Encumbered>().get_mut(player_entity) {
+        Some(encumbered) if encumbered.lagging => {
+            encumbered.lagging = false;
+            true
+        },
+        _ => false,
+    }
+}
+
+fn export_level(ecs: &mut World) -> RunState {
+    const NAME: &str = "level_export";
+    map_builder::export_level(ecs, NAME);
+    write!(ecs.fetch_mut::<GameLog>().new_entry(), 
+        "Level exported to {0}.txt and {0}.png", NAME).unwrap();
+    RunState::AwaitingInput
+}
@@ -5,6 +5,10 @@ pub struct RandomTable<T: Clone> {
     total_weight: i32,
 }
 
+impl<T: Clone> Default for RandomTable<T> {
+    fn default() -> Self { Self::new() }
+}
+
 impl<T: Clone> RandomTable<T> {
     pub fn new() -> Self { Self { entries: vec![], total_weight: 0 } }
 
@@ -27,6 +31,10 @@ impl<T: Clone> RandomTable<T> {
         self.total_weight = 0;
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
     pub fn roll(&self) -> &T {
         assert!(!self.entries.is_empty());
 
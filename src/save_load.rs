@@ -5,9 +5,26 @@ use specs::{
     prelude::*,
     saveload::*,
 };
-use crate::{comp::*, map::Map};
+use crate::{comp::*, map::{Map, LevelStash}};
 
 
+///Calls `$m` with the given arguments followed by every component that is saved,
+///the one list to add a saved component to
+macro_rules! with_saved_components {
+    ($m:ident!($($arg:expr),*)) => {
+        $m!($($arg),*, Position, Renderable, Player, Viewshed, Monster, 
+        Named, BlocksTile, CombatStats, SufferDamage, WantsToMelee, Item, Consumable, Ranged, InflictsDamage, 
+        AreaOfEffect, Confusion, ProvidesHealing, InBackpack, WantsToPickupItem, WantsToUseItem,
+        WantsToDropItem, Equippable, Equipped, AttackBonus, DefenseBonus, HungerClock, Nutritious,
+        EntryTrigger, SingleActivation, MagicMapper, Asleep, Unaware, Sneaking, MakesNoise,
+        IdleBehaviour, Npc, Stashed, Value, Wallet, Gold, Vendor, Stack, Weight, CarryCapacity,
+        Encumbered, Charges, Recharger, Mana, Spell, Spellbook, KnownSpell,
+        Teleporter, Pit, FiresArrows, SpawnsGas, Perception, WantsToSearch, WantsToDisarm
+        )
+    };
+}
+pub(crate) use with_saved_components;
+
 macro_rules! serialize_individually {
     ($ecs:expr, $ser:expr, $data:expr, $( $type:ty),*) => {
         $(
@@ -42,16 +59,14 @@ pub fn save_game(ecs: &mut World) {
         let map = ecs.get_mut::<Map>().unwrap().clone();
         let writer = File::create("./saved_map.json").unwrap();
         serde_json::to_writer(writer, &map).unwrap();
+
+        let writer = File::create("./saved_levels.json").unwrap();
+        serde_json::to_writer(writer, &*ecs.fetch::<LevelStash>()).unwrap();
     }
     let data = (ecs.entities(), ecs.read_storage::<SimpleMarker<SerializeMe>>());
     let writer = File::create("./saved_entities.json").unwrap();
     let mut serializer = serde_json::Serializer::new(writer);
-    serialize_individually!(ecs, serializer, data, Position, Renderable, Player, Viewshed, Monster, 
-        Named, BlocksTile, CombatStats, SufferDamage, WantsToMelee, Item, Consumable, Ranged, InflictsDamage, 
-        AreaOfEffect, Confusion, ProvidesHealing, InBackpack, WantsToPickupItem, WantsToUseItem,
-        WantsToDropItem, Equippable, Equipped, AttackBonus, DefenseBonus, HungerClock, Nutritious,
-        EntryTrigger, SingleActivation, MagicMapper
-    );
+    with_saved_components!(serialize_individually!(ecs, serializer, data));
 }
 
 
@@ -61,6 +76,11 @@ pub fn load_game(ecs: &mut World) {
         let mut map: Map = serde_json::from_str(&data).unwrap();
         map.realloc_content_index();
         ecs.insert(map);
+
+        let stash: LevelStash = std::fs::read_to_string("./saved_levels.json").ok()
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default();
+        ecs.insert(stash);
     }
     ecs.delete_all();
     let data = std::fs::read_to_string("./saved_entities.json").unwrap();
@@ -71,12 +91,7 @@ pub fn load_game(ecs: &mut World) {
             &mut ecs.write_storage::<SimpleMarker<SerializeMe>>(), 
             &mut ecs.write_resource::<SimpleMarkerAllocator<SerializeMe>>());
         
-        deserialize_individually!(ecs, de, d, Position, Renderable, Player, Viewshed, Monster, 
-            Named, BlocksTile, CombatStats, SufferDamage, WantsToMelee, Item, Consumable, Ranged, InflictsDamage, 
-            AreaOfEffect, Confusion, ProvidesHealing, InBackpack, WantsToPickupItem, WantsToUseItem,
-            WantsToDropItem, Equippable, Equipped, AttackBonus, DefenseBonus, HungerClock, Nutritious,
-            EntryTrigger, SingleActivation, MagicMapper
-        );
+        with_saved_components!(deserialize_individually!(ecs, de, d));
     }
 
     let (player, plp) = {
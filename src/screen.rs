@@ -1,6 +1,9 @@
+use std::collections::HashMap;
 use macroquad::prelude::*;
+use macroquad::models::{Mesh, Vertex, draw_mesh};
+use macroquad::window::gl_set_drawcall_buffer_capacity;
 
-use crate::util::to_cp437;
+use crate::{util::to_cp437, camera::Camera, settings::Tileset};
 
 use super::util::{
     IRect,
@@ -10,52 +13,366 @@ use super::util::{
 };
 
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
 struct Cell {
     glyph: Glyph,
     bg: [f32; 4],
     fg: [f32; 4],
+    ///Drawn instead of the glyph when there is a sprite font
+    sprite: Option<u16>,
+    ///Glyph or sprite shift in cells, the background stays put
+    offset: [f32; 2],
 }
 
+///The smallest console the UI is laid out for, a smaller window cuts it off
+pub const MIN_COLS: i32 = 80;
+pub const MIN_ROWS: i32 = 50;
+
+///Consoles composited on top of each other at `flush`, in this order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layer {
+    Map,
+    Entities,
+    Effects,
+    Ui,
+}
+
+///Quads in one mesh, so their vertices can be indexed with a `u16`
+const MESH_QUADS: usize = 8192;
+
+///A glyph atlas, drawn stretched to the screen's cell size
 #[derive(Debug)]
-pub struct Screen {
-    buffer: Grid<Cell>,
+pub struct Font {
     texture: Texture2D,
     glyph_size: Vec2,
-    scaled_glyph_size: Vec2,
-    cols: u8,
+    cols: u32,
+    first_glyph: u8,
 }
 
-impl Screen {
-    pub fn new(scr_width: i32, scr_height: i32, texture: Texture2D,
-               cols: u8, rows: u8, scale: Vec2) -> Self 
-    {
-        let glyph_size = Vec2::new(texture.width() / cols as f32, texture.height() / rows as f32);
+impl Font {
+    ///Loads a tileset's atlas, keying out its black background if it has one
+    pub async fn load(tileset: &Tileset) -> Self {
+        let mut image = load_image(&tileset.path).await
+            .unwrap_or_else(|e| panic!("failed to load {}: {}", tileset.path, e));
+        if tileset.black_background {
+            for px in image.get_image_data_mut() {
+                let lum = px[0].max(px[1]).max(px[2]);
+                *px = [255, 255, 255, (px[3] as u32 * lum as u32 / 255) as u8];
+            }
+        }
+        let texture = Texture2D::from_image(&image);
+        let glyph_size = Vec2::new(tileset.glyph_width as f32, tileset.glyph_height as f32);
         Self {
-            buffer: Grid::new(scr_width, scr_height, Cell::default()),
-            scaled_glyph_size: glyph_size * scale,
-            texture, cols, glyph_size,
+            cols: (texture.width() / glyph_size.x) as u32,
+            first_glyph: tileset.first_glyph,
+            texture, glyph_size,
+        }
+    }
+
+    ///Where the `n`th cell of the atlas is, in texture coordinates
+    fn uv_rect(&self, n: u32) -> Rect {
+        let offset = Vec2::new((n % self.cols) as f32, (n / self.cols) as f32);
+        let texture_size = Vec2::new(self.texture.width(), self.texture.height());
+        let size = self.glyph_size / texture_size;
+        Rect::new(0., 0., size.x, size.y).offset(offset * size)
+    }
+}
+
+///Every cell of a dense console is kept, a sparse one only has what was drawn to it
+enum Cells {
+    Dense {
+        cells: Grid<Cell>,
+        ///What the batch currently shows, `None` where it needs rebuilding
+        drawn: Grid<Option<Cell>>,
+    },
+    Sparse {
+        cells: HashMap<(i32, i32), Cell>,
+        ///Drawn anywhere, any number to a cell
+        loose: Vec<(Vec2, Cell)>,
+    },
+}
+
+struct Console {
+    cells: Cells,
+    font: usize,
+    batch: Batch,
+}
+
+impl Console {
+    fn new(cells: Cells, font: usize) -> Self {
+        Self { cells, font, batch: Batch::default() }
+    }
+
+    fn cell_mut(&mut self, x: i32, y: i32) -> &mut Cell {
+        match &mut self.cells {
+            Cells::Dense { cells, .. } => cells.get_mut(x, y),
+            Cells::Sparse { cells, .. } => cells.entry((x, y)).or_default(),
         }
     }
 
+    fn clear(&mut self) {
+        match &mut self.cells {
+            Cells::Dense { cells, .. } => cells.iter_mut().for_each(|c| *c = Cell::default()),
+            Cells::Sparse { cells, loose } => {
+                cells.clear();
+                loose.clear();
+            },
+        }
+    }
+
+    ///Brings the batch up to date, a dense console only rebuilds the cells that changed
+    fn update_batch(&mut self, font: &Font, sprites: Option<&Font>, cell_size: Vec2) {
+        let batch = &mut self.batch;
+        match &mut self.cells {
+            Cells::Dense { cells, drawn } => {
+                let len = (cells.width() * cells.height()) as usize;
+                if batch.len != len {
+                    *drawn = Grid::new(cells.width(), cells.height(), None);
+                    batch.reset(len, font, sprites);
+                }
+                for y in 0..cells.height() {
+                    for x in 0..cells.width() {
+                        let cell = cells.get(x, y);
+                        let shown = drawn.get_mut(x, y);
+                        if *shown == Some(*cell) { continue; }
+                        *shown = Some(*cell);
+                        let pos = Vec2::new(x as f32, y as f32) * cell_size;
+                        batch.set((y * cells.width() + x) as usize, pos, cell_size, cell, 
+                            font, sprites);
+                    }
+                }
+            },
+            Cells::Sparse { cells, loose } => {
+                batch.reset(cells.len() + loose.len(), font, sprites);
+                let cells = cells.iter().map(|((x, y), cell)| (Vec2::new(*x as f32, *y as f32), cell));
+                let loose = loose.iter().map(|(pos, cell)| (*pos, cell));
+                for (i, (pos, cell)) in cells.chain(loose).enumerate() {
+                    batch.set(i, pos * cell_size, cell_size, cell, font, sprites);
+                }
+            },
+        }
+    }
+}
+
+///Background, glyph and sprite quads of a console, split into meshes of `MESH_QUADS`
+#[derive(Default)]
+struct Batch {
+    bg: Vec<Mesh>,
+    fg: Vec<Mesh>,
+    ///Empty without a sprite font
+    sprites: Vec<Mesh>,
+    len: usize,
+}
+
+impl Batch {
+    ///Makes room for `len` blank quads
+    fn reset(&mut self, len: usize, font: &Font, sprites: Option<&Font>) {
+        let chunks = len.div_ceil(MESH_QUADS);
+        let mesh = |quads: usize, texture| Mesh {
+            vertices: vec![BLANK; quads * 4],
+            indices: (0..quads as u16)
+                .flat_map(|q| [0, 1, 2, 0, 2, 3].map(|i| q * 4 + i))
+                .collect(),
+            texture,
+        };
+        self.bg.clear();
+        self.fg.clear();
+        self.sprites.clear();
+        for chunk in 0..chunks {
+            let quads = (len - chunk * MESH_QUADS).min(MESH_QUADS);
+            self.bg.push(mesh(quads, None));
+            self.fg.push(mesh(quads, Some(font.texture)));
+            if let Some(sprites) = sprites {
+                self.sprites.push(mesh(quads, Some(sprites.texture)));
+            }
+        }
+        self.len = len;
+    }
+
+    fn set(&mut self, i: usize, pos: Vec2, size: Vec2, cell: &Cell, font: &Font, 
+        sprites: Option<&Font>) 
+    {
+        let (chunk, v) = (i / MESH_QUADS, i % MESH_QUADS * 4);
+        let bg = match cell.bg[3] > 0. {
+            true => quad(pos, size, Rect::new(0., 0., 0., 0.), Color::from(cell.bg)),
+            false => [BLANK; 4],
+        };
+        self.bg[chunk].vertices[v..v + 4].copy_from_slice(&bg);
+
+        let pos = pos + Vec2::from(cell.offset) * size;
+        let (fg, sprite) = match (cell.sprite, sprites) {
+            (Some(n), Some(sprites)) => 
+                ([BLANK; 4], quad(pos, size, sprites.uv_rect(n as u32), Color::from(cell.fg))),
+            _ if cell.glyph != 0 && cell.glyph >= font.first_glyph => {
+                let uv = font.uv_rect((cell.glyph - font.first_glyph) as u32);
+                (quad(pos, size, uv, Color::from(cell.fg)), [BLANK; 4])
+            },
+            _ => ([BLANK; 4], [BLANK; 4]),
+        };
+        self.fg[chunk].vertices[v..v + 4].copy_from_slice(&fg);
+        if let Some(mesh) = self.sprites.get_mut(chunk) {
+            mesh.vertices[v..v + 4].copy_from_slice(&sprite);
+        }
+    }
+
+    fn draw(&self) {
+        for mesh in self.bg.iter().chain(self.sprites.iter()).chain(self.fg.iter()) {
+            draw_mesh(mesh);
+        }
+    }
+}
+
+const BLANK: Vertex = Vertex { position: Vec3::ZERO, uv: Vec2::ZERO, color: Color::new(0., 0., 0., 0.) };
+
+fn quad(pos: Vec2, size: Vec2, uv: Rect, color: Color) -> [Vertex; 4] {
+    let corner = |dx: f32, dy: f32| Vertex {
+        position: Vec3::new(pos.x + dx * size.x, pos.y + dy * size.y, 0.),
+        uv: Vec2::new(uv.x + dx * uv.w, uv.y + dy * uv.h),
+        color,
+    };
+    [corner(0., 0.), corner(1., 0.), corner(1., 1.), corner(0., 1.)]
+}
+
+pub struct Screen {
+    consoles: Vec<Console>,
+    fonts: Vec<Font>,
+    ///Sprite atlas for the tile layers, if one was loaded
+    sprites: Option<Font>,
+    active: Layer,
+    ///Shift of the map, entity and effect layers in cells
+    scroll: Vec2,
+    ///Cell under the mouse this frame and the one before
+    mouse: (i32, i32),
+    last_mouse: (i32, i32),
+    width: i32,
+    height: i32,
+    cell_size: Vec2,
+}
+
+impl Screen {
+    ///Cells are sized after the `tiles` font, which the map, entities and effects use,
+    ///the UI is drawn with `text`. Cells drawn with `draw_sprite` use `sprites` if there is one
+    pub fn new(tiles: Font, text: Font, sprites: Option<Font>, scale: f32) -> Self {
+        gl_set_drawcall_buffer_capacity(MESH_QUADS * 4 + 4, MESH_QUADS * 6 + 6);
+        let dense = || Cells::Dense {
+            cells: Grid::new(MIN_COLS, MIN_ROWS, Cell::default()),
+            drawn: Grid::new(MIN_COLS, MIN_ROWS, None),
+        };
+        let sparse = || Cells::Sparse { cells: HashMap::new(), loose: vec![] };
+        let mut screen = Self {
+            consoles: vec![
+                Console::new(dense(), 0),
+                Console::new(sparse(), 0),
+                Console::new(sparse(), 0),
+                Console::new(dense(), 1),
+            ],
+            cell_size: tiles.glyph_size * scale,
+            fonts: vec![tiles, text],
+            sprites,
+            active: Layer::Ui,
+            scroll: Vec2::ZERO,
+            mouse: (0, 0),
+            last_mouse: (0, 0),
+            width: MIN_COLS,
+            height: MIN_ROWS,
+        };
+        screen.fit_to_window();
+        screen
+    }
+
+    ///Size of the console in glyphs
+    pub fn width(&self) -> i32 { self.width }
+    pub fn height(&self) -> i32 { self.height }
+
+    ///Reflows the console to as many glyphs as fit in the window, returns whether it changed
+    pub fn fit_to_window(&mut self) -> bool {
+        let cols = ((screen_width() / self.cell_size.x) as i32).max(MIN_COLS);
+        let rows = ((screen_height() / self.cell_size.y) as i32).max(MIN_ROWS);
+        if cols == self.width && rows == self.height {
+            return false;
+        }
+        (self.width, self.height) = (cols, rows);
+        for console in self.consoles.iter_mut() {
+            if let Cells::Dense { cells, .. } = &mut console.cells {
+                cells.resize(cols, rows, Cell::default());
+            }
+        }
+        true
+    }
+
+    ///Clears every layer and makes the UI the one drawn to
     pub fn clear(&mut self) {
-        for i in self.buffer.iter_mut() {
-            *i = Cell::default();
+        for console in self.consoles.iter_mut() {
+            console.clear();
         }
+        self.active = Layer::Ui;
+        let (x, y) = mouse_position();
+        self.last_mouse = self.mouse;
+        self.mouse = ((x / self.cell_size.x) as i32, (y / self.cell_size.y) as i32);
+    }
+
+    ///Console cell under the mouse
+    pub fn mouse_cell(&self) -> (i32, i32) {
+        self.mouse
+    }
+
+    ///Whether the mouse went to another cell since the last frame
+    pub fn mouse_moved(&self) -> bool {
+        self.mouse != self.last_mouse
+    }
+
+    ///Makes the following draw calls go to `layer`
+    pub fn set_layer(&mut self, layer: Layer) {
+        self.active = layer;
+    }
+
+    fn cell_mut(&mut self, x: i32, y: i32) -> &mut Cell {
+        self.consoles[self.active as usize].cell_mut(x, y)
     }
 
     pub fn set_bg(&mut self, x: i32, y: i32, bg: [f32; 4]) {
-        self.buffer.get_mut(x, y).bg = bg;
+        self.cell_mut(x, y).bg = bg;
     }
 
     pub fn draw_glyph(&mut self, x: i32, y: i32, glyph: Glyph, fg: [f32; 4], bg: [f32; 4]) {
-        *self.buffer.get_mut(x, y) = Cell { glyph, fg, bg };
+        *self.cell_mut(x, y) = Cell { glyph, fg, bg, ..Default::default() };
+    }
+
+    ///Draws sprite `sprite` tinted by `tint`, `glyph` is drawn instead if there's no sprite font
+    pub fn draw_sprite(&mut self, x: i32, y: i32, sprite: u16, glyph: Glyph, tint: [f32; 4], 
+        bg: [f32; 4]) 
+    {
+        let sprite = Some(sprite);
+        *self.cell_mut(x, y) = Cell { glyph, fg: tint, bg, sprite, ..Default::default() };
+    }
+
+    ///Shifts what was drawn at `(x, y)` by `offset` cells
+    pub fn set_offset(&mut self, x: i32, y: i32, offset: Vec2) {
+        self.cell_mut(x, y).offset = offset.into();
+    }
+
+    ///Draws a glyph at a fractional cell position, on top of the cells there.
+    ///A dense layer has no room for it and draws it at the nearest cell
+    pub fn draw_particle(&mut self, pos: Vec2, glyph: Glyph, fg: [f32; 4], bg: [f32; 4]) {
+        let cell = Cell { glyph, fg, bg, ..Default::default() };
+        match &mut self.consoles[self.active as usize].cells {
+            Cells::Sparse { loose, .. } => loose.push((pos, cell)),
+            Cells::Dense { .. } => {
+                let (x, y) = (pos.x.round() as i32, pos.y.round() as i32);
+                *self.cell_mut(x, y) = cell;
+            },
+        }
+    }
+
+    ///Draws every layer but the UI shifted by `scroll` cells
+    pub fn set_scroll(&mut self, scroll: Vec2) {
+        self.scroll = scroll;
     }
 
     pub fn draw_box(&mut self, bounds: IRect, fg: [f32; 4], bg: [f32; 4]) {
-        for y in bounds.y..=bounds.yy {
-            for x in bounds.x..=bounds.xx {
-                *self.buffer.get_mut(x, y) = Cell::default();
+        for y in bounds.y+1..bounds.yy {
+            for x in bounds.x+1..bounds.xx {
+                self.draw_glyph(x, y, 0, fg, bg);
             }
         }
 
@@ -79,60 +396,54 @@ impl Screen {
         for ch in text.chars() {
             self.draw_glyph(x, y, to_cp437(ch), fg, bg);
             x += 1;
-            if x >= self.buffer.width() { break; }
+            if x >= self.width { break; }
         }
     }
 
     pub fn draw_text_centered(&mut self, y: i32, fg: [f32; 4], bg: [f32; 4], text: &str) {
-        let x = (self.buffer.width() - text.len() as i32) / 2;
+        let x = (self.width - text.len() as i32) / 2;
         self.draw_text(x.max(0), y, fg, bg, text);
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn draw_bar_horizontal(&mut self, x: i32, y: i32, width: i32, value: i32, 
         max_value: i32, fg: [f32; 4], bg: [f32; 4]) 
     {
         let xx = x + width * value / max_value;
-        for x in x..xx.min(self.buffer.width()) {
+        for x in x..xx.min(self.width) {
             self.draw_glyph(x, y, to_cp437('▓'), fg, bg);
         }
-        for x in xx..(x + width).min(self.buffer.width()) {
+        for x in xx..(x + width).min(self.width) {
             self.draw_glyph(x, y, to_cp437('░'), fg, bg);
         }
     }
 
-    pub fn flush(&self) {
+    ///Draws the layers in order, each in one call for backgrounds and one for glyphs
+    pub fn flush(&mut self) {
         clear_background(BLACK);
-        for y in 0..self.buffer.height() {
-            for x in 0..self.buffer.width() {
-                let pos = Vec2::new(x as f32, y as f32) * self.scaled_glyph_size;
-                let bg = self.buffer.get(x, y).bg;
-                let bg = Color::new(bg[0], bg[1], bg[2], bg[3]);
-                draw_rectangle(pos.x, pos.y, self.scaled_glyph_size.x, self.scaled_glyph_size.y, bg);
-            }
+        let shift = self.scroll * self.cell_size;
+        let scrolled = shift != Vec2::ZERO;
+        if scrolled {
+            set_camera(&Camera2D::from_display_rect(
+                Rect::new(-shift.x, -shift.y, screen_width(), screen_height())));
         }
-
-        for y in 0..self.buffer.height() {
-            for x in 0..self.buffer.width() {
-                let Cell { glyph, fg, bg: _ } = self.buffer.get(x, y);
-                let r = self.get_rect(*glyph);
-                let pos = Vec2::new(x as f32, y as f32) * self.scaled_glyph_size;
-                let fg = Color::new(fg[0], fg[1], fg[2], fg[3]);
-
-                draw_texture_ex(self.texture, pos.x, pos.y, fg, DrawTextureParams {
-                    source: Some(r),
-                    dest_size: Some(self.scaled_glyph_size),
-                    ..DrawTextureParams::default()
-                })
+        for (i, console) in self.consoles.iter_mut().enumerate() {
+            if scrolled && i == Layer::Ui as usize {
+                set_default_camera();
             }
+            let sprites = self.sprites.as_ref().filter(|_| console.font == 0);
+            console.update_batch(&self.fonts[console.font], sprites, self.cell_size);
+            console.batch.draw();
         }
     }
 
-    pub fn draw_djmap(&mut self, dm: &DjMap) {
+    pub fn draw_djmap(&mut self, dm: &DjMap, camera: &Camera) {
         let center_color = Vec3::new(1., 0., 0.);
         let border_color = Vec3::new(0., 0., 1.);
-        let bounds = dm.bounds().intersection(
-            &IRect::new(0, 0, self.buffer.width(), self.buffer.height())
-        ).unwrap();
+        let bounds = match dm.bounds().intersection(&camera.view()) {
+            Some(bounds) => bounds,
+            None => return,
+        };
         let max = dm.iter().max();
         if max.is_none() { return; }
         let max = *max.unwrap();
@@ -143,15 +454,10 @@ impl Screen {
                 if d < 0 { continue; }
                 let p = d as f32 / max as f32;
                 let rgb =  p * (border_color - center_color) + center_color;
-                self.set_bg(x, y, [rgb.x, rgb.y, rgb.z, 1.]);
+                if let Some((sx, sy)) = camera.to_screen(x, y) {
+                    self.set_bg(sx, sy, [rgb.x, rgb.y, rgb.z, 1.]);
+                }
             }
         }
     }
-
-    fn get_rect(&self, n: u8) -> Rect {
-        let offset = Vec2::new((n % self.cols) as f32, (n / self.cols) as f32);
-        Rect::new(0., 0., self.glyph_size.x, self.glyph_size.y)
-            .offset(offset * self.glyph_size)
-    }
 }
-
@@ -0,0 +1,86 @@
+use serde::{Serialize, Deserialize};
+
+pub const SETTINGS_FILE: &str = "settings.json";
+
+///A font atlas with glyphs laid out in cp437 order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tileset {
+    pub path: String,
+    pub glyph_width: u32,
+    pub glyph_height: u32,
+    ///Glyph in the atlas's top left cell, the ones before it are drawn blank
+    #[serde(default)]
+    pub first_glyph: u8,
+    ///Glyphs are drawn on solid black, which is made transparent on load
+    #[serde(default)]
+    pub black_background: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TilesetChoice {
+    Atlas,
+    Consolas,
+    Custom(Tileset),
+}
+
+impl TilesetChoice {
+    pub fn tileset(&self) -> Tileset {
+        match self {
+            TilesetChoice::Atlas => Tileset {
+                path: "atlas.png".to_owned(),
+                glyph_width: 16,
+                glyph_height: 16,
+                first_glyph: 0,
+                black_background: false,
+            },
+            TilesetChoice::Consolas => Tileset {
+                path: "Consolas.png".to_owned(),
+                glyph_width: 14,
+                glyph_height: 24,
+                first_glyph: 1,
+                black_background: true,
+            },
+            TilesetChoice::Custom(tileset) => tileset.clone(),
+        }
+    }
+}
+
+///Read from `settings.json` if there is one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub tileset: TilesetChoice,
+    ///Used for the UI, the map tileset if not set
+    pub text_tileset: Option<TilesetChoice>,
+    ///Glyph scale, doesn't have to be a whole number
+    pub scale: f32,
+    ///Sprite sheet description for tile mode, skipped if the file is missing
+    pub sprites: Option<String>,
+    ///Whether the game waits for moves, attacks and projectiles to finish animating
+    pub wait_for_animations: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self { 
+            tileset: TilesetChoice::Atlas, 
+            text_tileset: None, 
+            scale: 1., 
+            sprites: Some("tiles.json".to_owned()), 
+            wait_for_animations: true,
+        }
+    }
+}
+
+impl Settings {
+    pub fn load(path: &str) -> Self {
+        let data = match std::fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(_) => return Self::default(),
+        };
+        let settings: Self = serde_json::from_str(&data)
+            .unwrap_or_else(|e| panic!("failed to parse {}: {}", path, e));
+        assert!(settings.scale > 0., "{}: scale must be positive", path);
+        settings
+    }
+}
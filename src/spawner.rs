@@ -1,11 +1,15 @@
 use specs::{prelude::*, saveload::{MarkedBuilder, SimpleMarker}};
-use smallvec::smallvec;
+use smallvec::{smallvec, SmallVec};
+use rand::{thread_rng, Rng, seq::SliceRandom};
 use super::{
     comp::*,
+    util::IRect,
     util::Glyph,
     util::to_cp437,
     util::colors::*,
     random_table::RandomTable,
+    inventory,
+    sprites,
 };
 
 #[derive(Debug, Clone, Copy)]
@@ -22,29 +26,140 @@ enum SpawnOption {
     TowerShield,
     Rations,
     MagicMappingScroll,
+    RechargingScroll,
+    MagicMissileWand,
+    ConfusionWand,
+    MagicMissileBook,
+    FireballBook,
+    ConfusionBook,
+    HealingBook,
     BearTrap,
+    TeleportTrap,
+    AlarmTrap,
+    PitTrap,
+    PoisonGasTrap,
+    ConfusionGasTrap,
+    ArrowTrap,
+    Gold,
+    Villager,
+    Grocer,
+    Alchemist,
+    Blacksmith,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnKind {
+    Monster,
+    Item,
+    Trap,
+    Npc,
+}
+
+impl SpawnOption {
+    ///The option that spawns an entity `Named` `name`
+    fn from_name(name: &str) -> Option<Self> {
+        use SpawnOption::*;
+        Some(match name {
+            "Goblin" => Goblin,
+            "Orc" => Orc,
+            "Health potion" => HealthPotion,
+            "Fireball scroll" => FireballScroll,
+            "Confusion scroll" => ConfusionScroll,
+            "Magic missile scroll" => MagicMissileScroll,
+            "Dagger" => Dagger,
+            "Shield" => Shield,
+            "Longsword" => LongSword,
+            "Tower shield" => TowerShield,
+            "Rations" => Rations,
+            "Scroll of Magic Mapping" => MagicMappingScroll,
+            "Scroll of Recharging" => RechargingScroll,
+            "Wand of magic missile" => MagicMissileWand,
+            "Wand of confusion" => ConfusionWand,
+            "Spellbook of Magic Missile" => MagicMissileBook,
+            "Spellbook of Fireball" => FireballBook,
+            "Spellbook of Confusion" => ConfusionBook,
+            "Spellbook of Healing" => HealingBook,
+            "Bear trap" => BearTrap,
+            "Teleport trap" => TeleportTrap,
+            "Alarm trap" => AlarmTrap,
+            "Pit" => PitTrap,
+            "Poison gas trap" => PoisonGasTrap,
+            "Confusion gas trap" => ConfusionGasTrap,
+            "Arrow trap" => ArrowTrap,
+            "Gold" => Gold,
+            "Villager" => Villager,
+            "Grocer" => Grocer,
+            "Alchemist" => Alchemist,
+            "Blacksmith" => Blacksmith,
+            _ => return None,
+        })
+    }
+
+    fn kind(self) -> SpawnKind {
+        use SpawnOption::*;
+        match self {
+            Goblin | Orc => SpawnKind::Monster,
+            BearTrap | TeleportTrap | AlarmTrap | PitTrap | PoisonGasTrap | ConfusionGasTrap
+                | ArrowTrap => SpawnKind::Trap,
+            Villager | Grocer | Alchemist | Blacksmith => SpawnKind::Npc,
+            _ => SpawnKind::Item,
+        }
+    }
+}
+
+const RESTOCK_TURNS: i32 = 300;
+
 pub struct Spawner {
     table: RandomTable<SpawnOption>,
+    kind_tables: [RandomTable<SpawnOption>; 4],
     depth: i32,
+    area: Option<IRect>,
+    waypoints: Vec<(i32, i32)>,
 }
 
 impl Spawner {
     pub fn new(depth: i32) -> Self {
         let mut inst = Self { 
             table: RandomTable::new(), 
-            depth 
+            kind_tables: Default::default(),
+            depth,
+            area: None,
+            waypoints: vec![],
         };
         inst.update_table();
         inst
     }
 
     pub fn spawn(&mut self, ecs: &mut World, x: i32, y: i32) {
+        let opt = *self.table.roll();
+        self.spawn_option(ecs, x, y, opt);
+    }
+
+    ///Whether `spawn_named` knows how to spawn `name`
+    pub fn can_spawn_named(name: &str) -> bool {
+        SpawnOption::from_name(name).is_some()
+    }
+
+    ///Spawns the entity that would be `Named` `name`, if there's such a thing
+    pub fn spawn_named(&mut self, ecs: &mut World, x: i32, y: i32, name: &str) {
+        if let Some(opt) = SpawnOption::from_name(name) {
+            self.spawn_option(ecs, x, y, opt);
+        }
+    }
+
+    ///Spawns something of the given kind, e.g. for prefab legend characters
+    pub fn spawn_kind(&mut self, ecs: &mut World, x: i32, y: i32, kind: SpawnKind) {
+        let table = &self.kind_tables[kind as usize];
+        if table.is_empty() { return; }
+        let opt = *table.roll();
+        self.spawn_option(ecs, x, y, opt);
+    }
+
+    fn spawn_option(&self, ecs: &mut World, x: i32, y: i32, opt: SpawnOption) -> Entity {
         use SpawnOption::*;
-        match *self.table.roll() {
-            Goblin => goblin(ecs, x, y),
-            Orc => orc(ecs, x, y),
+        let e = match opt {
+            Goblin => goblin(ecs, x, y, self.idle_behaviour(x, y)),
+            Orc => orc(ecs, x, y, self.idle_behaviour(x, y)),
             HealthPotion => health_potion(ecs, x, y),
             FireballScroll => fireball_scroll(ecs, x, y),
             ConfusionScroll => confusion_scroll(ecs, x, y),
@@ -55,7 +170,101 @@ impl Spawner {
             TowerShield => tower_shield(ecs, x, y),
             Rations => rations(ecs, x, y),
             MagicMappingScroll => magic_mapping_scroll(ecs, x, y),
+            RechargingScroll => recharging_scroll(ecs, x, y),
+            MagicMissileWand => magic_missile_wand(ecs, x, y),
+            ConfusionWand => confusion_wand(ecs, x, y),
+            MagicMissileBook => magic_missile_book(ecs, x, y),
+            FireballBook => fireball_book(ecs, x, y),
+            ConfusionBook => confusion_book(ecs, x, y),
+            HealingBook => healing_book(ecs, x, y),
             BearTrap => bear_trap(ecs, x, y),
+            TeleportTrap => trap(ecs, x, y, "Teleport trap", CYAN).with(Teleporter {}).build(),
+            AlarmTrap => trap(ecs, x, y, "Alarm trap", YELLOW)
+                .with(MakesNoise { volume: 40 })
+                .build(),
+            PitTrap => trap(ecs, x, y, "Pit", GRAY)
+                .with(Pit {})
+                .with(InflictsDamage { damage: 3 })
+                .build(),
+            PoisonGasTrap => trap(ecs, x, y, "Poison gas trap", GREEN)
+                .with(SpawnsGas { kind: GasKind::Poison })
+                .with(SingleActivation {})
+                .build(),
+            ConfusionGasTrap => trap(ecs, x, y, "Confusion gas trap", PINK)
+                .with(SpawnsGas { kind: GasKind::Confusion })
+                .with(SingleActivation {})
+                .build(),
+            ArrowTrap => trap(ecs, x, y, "Arrow trap", WHITE)
+                .with(FiresArrows {})
+                .with(InflictsDamage { damage: 5 })
+                .build(),
+            Gold => gold(ecs, x, y, thread_rng().gen_range(5..=10) * self.depth.max(1)),
+            Villager => villager(ecs, x, y, self.area),
+            Grocer => self.vendor(ecs, x, y, "Grocer", Wares::General),
+            Alchemist => self.vendor(ecs, x, y, "Alchemist", Wares::Alchemy),
+            Blacksmith => self.vendor(ecs, x, y, "Blacksmith", Wares::Smithing),
+        };
+        sprites::assign(ecs, e);
+        e
+    }
+
+    fn vendor(&self, ecs: &mut World, x: i32, y: i32, name: &str, wares: Wares) -> Entity {
+        let e = npc(ecs, x, y, to_cp437('☻'), GOLD, name.to_owned(), 
+            IdleBehaviour::Guard { post: (x, y) });
+        ecs.write_storage::<Vendor>().insert(e, Vendor { wares, restock_in: RESTOCK_TURNS })
+            .expect("failed to insert Vendor");
+        ecs.write_storage::<Wallet>().insert(e, Wallet { gold: 0 })
+            .expect("failed to insert Wallet");
+        self.stock(ecs, e, wares);
+        e
+    }
+
+    ///Fills up the vendor's backpack and wallet
+    fn stock(&self, ecs: &mut World, vendor: Entity, wares: Wares) {
+        const VENDOR_STOCK: i32 = 8;
+        const VENDOR_GOLD: i32 = 150;
+        use SpawnOption::*;
+        let weights: &[(SpawnOption, i32)] = match wares {
+            Wares::General => &[(Rations, 5), (HealthPotion, 3), (Dagger, 1), (Shield, 1)],
+            Wares::Alchemy => &[(HealthPotion, 4), (MagicMissileScroll, 3), (ConfusionScroll, 2),
+                (FireballScroll, 2), (MagicMappingScroll, 2), (RechargingScroll, 1), 
+                (MagicMissileWand, 1), (ConfusionWand, 1), (MagicMissileBook, 1), 
+                (FireballBook, 1), (ConfusionBook, 1), (HealingBook, 1)],
+            Wares::Smithing => &[(Dagger, 3), (Shield, 3), (LongSword, 2), (TowerShield, 2)],
+        };
+        let mut table = RandomTable::new();
+        table.extend(weights.iter().copied());
+
+        let in_stock: i32 = (&ecs.read_storage::<InBackpack>(), ecs.read_storage::<Stack>().maybe())
+            .join()
+            .filter(|(bp, _)| bp.owner == vendor)
+            .map(|(_, stack)| stack.map_or(1, |s| s.count))
+            .sum();
+        for _ in in_stock..VENDOR_STOCK {
+            let item = self.spawn_option(ecs, 0, 0, *table.roll());
+            ecs.write_storage::<Position>().remove(item);
+            ecs.write_storage::<InBackpack>().insert(item, InBackpack { owner: vendor })
+                .expect("failed to insert InBackpack");
+            inventory::restack(ecs, item);
+        }
+
+        if let Some(wallet) = ecs.write_storage::<Wallet>().get_mut(vendor) {
+            wallet.gold = wallet.gold.max(VENDOR_GOLD);
+        }
+    }
+
+    ///Counts down the vendors' restock timers and restocks the ones that are due
+    pub fn restock_vendors(&self, ecs: &mut World) {
+        let due: Vec<_> = (&ecs.entities(), &mut ecs.write_storage::<Vendor>()).join()
+            .filter_map(|(e, vendor)| {
+                vendor.restock_in -= 1;
+                if vendor.restock_in > 0 { return None; }
+                vendor.restock_in = RESTOCK_TURNS;
+                Some((e, vendor.wares))
+            })
+            .collect();
+        for (vendor, wares) in due {
+            self.stock(ecs, vendor, wares);
         }
     }
 
@@ -65,6 +274,38 @@ impl Spawner {
         self.update_table();
     }
 
+    ///The area monsters spawned from now on will wander around in
+    pub fn set_area(&mut self, area: Option<IRect>) {
+        self.area = area;
+    }
+
+    ///Points of interest monsters may patrol between, e.g. room centers
+    pub fn set_waypoints(&mut self, waypoints: Vec<(i32, i32)>) {
+        self.waypoints = waypoints;
+    }
+
+    fn idle_behaviour(&self, x: i32, y: i32) -> IdleBehaviour {
+        const WANDER_RADIUS: i32 = 5;
+        let mut rng = thread_rng();
+        let area = self.area.unwrap_or_else(|| IRect::new(
+            x - WANDER_RADIUS, y - WANDER_RADIUS, 
+            2 * WANDER_RADIUS + 1, 2 * WANDER_RADIUS + 1
+        ));
+
+        match rng.gen_range(0..10) {
+            0..=3 => IdleBehaviour::Wander { area },
+            4..=6 if self.waypoints.len() >= 2 => {
+                let n = rng.gen_range(2..=3.min(self.waypoints.len()));
+                let waypoints: SmallVec<[(i32, i32); 4]> = self.waypoints
+                    .choose_multiple(&mut rng, n)
+                    .cloned()
+                    .collect();
+                IdleBehaviour::Patrol { waypoints, next: 0 }
+            },
+            _ => IdleBehaviour::Guard { post: (x, y) },
+        }
+    }
+
     fn update_table(&mut self) {
         use SpawnOption::*;
         let d = self.depth;
@@ -72,59 +313,126 @@ impl Spawner {
             (Goblin, 10), (Orc, 1 + d),
             (HealthPotion, 7), (FireballScroll, 2 + d), (ConfusionScroll, 2 + d),
             (MagicMissileScroll, 4), (Dagger, 3), (Shield, 3),
-            (LongSword, d - 1), (TowerShield, d - 1), (Rations, 10),
-            (MagicMappingScroll, 2), (BearTrap, 5),
+            (LongSword, (d - 1).max(0)), (TowerShield, (d - 1).max(0)), (Rations, 10),
+            (MagicMappingScroll, 2), (RechargingScroll, 1), (MagicMissileWand, 1 + d / 2),
+            (ConfusionWand, 1 + d / 2), (MagicMissileBook, 1), (FireballBook, d / 2),
+            (ConfusionBook, d / 2), (HealingBook, 1), (BearTrap, 5), (Gold, 6), (Villager, 3),
+            (TeleportTrap, 2), (AlarmTrap, 2), (PitTrap, 1 + d / 2), (PoisonGasTrap, 1 + d / 2),
+            (ConfusionGasTrap, 1 + d / 2), (ArrowTrap, 2 + d / 2),
         ];
         self.table.clear();
-        self.table.extend(weights.into_iter());
+        self.table.extend(weights.into_iter()
+            .filter(|(opt, _)| opt.kind() != SpawnKind::Npc));
+
+        for table in self.kind_tables.iter_mut() {
+            table.clear();
+        }
+        for (opt, weight) in weights {
+            self.kind_tables[opt.kind() as usize].add(opt, weight);
+        }
     }
 }
 
 pub fn player(ecs: &mut World, x: i32, y: i32) -> Entity {
-    ecs.create_entity()
+    let e = ecs.create_entity()
         .with(Position { x, y })
         .with(Renderable { 
             glyph: to_cp437('@'), 
             fg: YELLOW, 
             bg: BLACK,
             order: 1,
+            sprite: None,
         })
         .with(Viewshed { range: 8, visible_tiles: smallvec![], dirty: true })
         .with(Player{})
         .with(Named("Player".to_owned()))
         .with(CombatStats{ max_hp: 30, hp: 30, defense: 20, power: 5 })
         .with(HungerClock { state: HungerState::WellFed, duration: 20 })
+        .with(Wallet { gold: 30 })
+        .with(CarryCapacity { lbs: 40 })
+        .with(Perception { bonus: 1 })
+        .with(Mana { current: 10, max: 10, regen_in: 4 })
         .marked::<SimpleMarker<SerializeMe>>()
-        .build()
+        .build();
+    sprites::assign(ecs, e);
+    e
 }
 
-fn orc(ecs: &mut World, x: i32, y: i32) {
-    monster(ecs, x, y, to_cp437('o'), "Orc".to_owned())
+fn orc(ecs: &mut World, x: i32, y: i32, idle: IdleBehaviour) -> Entity {
+    monster(ecs, x, y, to_cp437('o'), "Orc".to_owned(), idle)
 }
 
-fn goblin(ecs: &mut World, x: i32, y: i32) {
-    monster(ecs, x, y, to_cp437('g'), "Goblin".to_owned())
+fn goblin(ecs: &mut World, x: i32, y: i32, idle: IdleBehaviour) -> Entity {
+    monster(ecs, x, y, to_cp437('g'), "Goblin".to_owned(), idle)
 }
 
-fn monster(ecs: &mut World, x: i32, y: i32, glyph: Glyph, name: String) {
-    ecs.create_entity()
+fn monster(ecs: &mut World, x: i32, y: i32, glyph: Glyph, name: String, idle: IdleBehaviour) -> Entity {
+    let mut builder = ecs.create_entity();
+    match thread_rng().gen_range(0..3) {
+        0 => builder = builder.with(Asleep {}).with(Unaware {}),
+        1 => builder = builder.with(Unaware {}),
+        _ => (),
+    };
+
+    builder
         .with(Position { x, y })
         .with(Renderable {
             glyph, 
             fg: RED, 
             bg: BLACK,
             order: 1,
+            sprite: None,
         })
         .with(Viewshed { range: 8, visible_tiles: smallvec![], dirty: true })
         .with(Monster {})
         .with(Named(name))
         .with(BlocksTile {})
         .with(CombatStats { max_hp: 16, hp: 16, defense: 1, power: 4 })
+        .with(idle)
         .marked::<SimpleMarker<SerializeMe>>()
-        .build();
+        .build()
+}
+
+fn villager(ecs: &mut World, x: i32, y: i32, area: Option<IRect>) -> Entity {
+    const WANDER_RADIUS: i32 = 6;
+    let area = area.unwrap_or_else(|| IRect::new(
+        x - WANDER_RADIUS, y - WANDER_RADIUS, 
+        2 * WANDER_RADIUS + 1, 2 * WANDER_RADIUS + 1
+    ));
+    npc(ecs, x, y, to_cp437('☺'), LIGHTGRAY, "Villager".to_owned(), IdleBehaviour::Wander { area })
 }
 
-fn health_potion(ecs: &mut World, x: i32, y: i32) {
+fn gold(ecs: &mut World, x: i32, y: i32, amount: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: to_cp437('$'),
+            fg: GOLD,
+            bg: BLACK,
+            order: 2,
+            sprite: None,
+        })
+        .with(Named("Gold".to_owned()))
+        .with(Item {})
+        .with(Gold { amount })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+fn npc(ecs: &mut World, x: i32, y: i32, glyph: Glyph, fg: [f32; 4], name: String, idle: IdleBehaviour) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable { glyph, fg, bg: BLACK, order: 1, sprite: None })
+        .with(Viewshed { range: 8, visible_tiles: smallvec![], dirty: true })
+        .with(Npc {})
+        .with(Named(name))
+        .with(BlocksTile {})
+        .with(idle)
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+fn health_potion(ecs: &mut World, x: i32, y: i32) -> Entity {
     ecs.create_entity()
         .with(Position { x, y })
         .with(Renderable {
@@ -132,16 +440,20 @@ fn health_potion(ecs: &mut World, x: i32, y: i32) {
             fg: MAGENTA,
             bg: BLACK,
             order: 2,
+            sprite: None,
         })
         .with(Named("Health potion".to_owned()))
         .with(Item {})
+        .with(Value { gold: 20 })
+        .with(Weight { lbs: 1 })
+        .with(Stack { count: 1 })
         .with(ProvidesHealing { heal_amount: 8 })
         .with(Consumable {})
         .marked::<SimpleMarker<SerializeMe>>()
-        .build();
+        .build()
 }
 
-fn magic_missile_scroll(ecs: &mut World, x: i32, y: i32) {
+fn magic_missile_scroll(ecs: &mut World, x: i32, y: i32) -> Entity {
     ecs.create_entity()
         .with(Position { x, y})
         .with(Renderable {
@@ -149,17 +461,22 @@ fn magic_missile_scroll(ecs: &mut World, x: i32, y: i32) {
             fg: CYAN,
             bg: BLACK,
             order: 2,
+            sprite: None,
         })
         .with(Named("Magic missile scroll".to_owned()))
+        .with(MakesNoise { volume: 8 })
         .with(Item {})
+        .with(Value { gold: 30 })
+        .with(Weight { lbs: 1 })
+        .with(Stack { count: 1 })
         .with(Consumable {})
         .with(Ranged { range: 6 })
         .with(InflictsDamage { damage: 8 })
         .marked::<SimpleMarker<SerializeMe>>()
-        .build();
+        .build()
 }
 
-fn fireball_scroll(ecs: &mut World, x: i32, y: i32) {
+fn fireball_scroll(ecs: &mut World, x: i32, y: i32) -> Entity {
     ecs.create_entity()
         .with(Position { x, y})
         .with(Renderable {
@@ -167,18 +484,23 @@ fn fireball_scroll(ecs: &mut World, x: i32, y: i32) {
             fg: ORANGE,
             bg: BLACK,
             order: 2,
+            sprite: None,
         })
         .with(Named("Fireball scroll".to_owned()))
+        .with(MakesNoise { volume: 14 })
         .with(Item {})
+        .with(Value { gold: 50 })
+        .with(Weight { lbs: 1 })
+        .with(Stack { count: 1 })
         .with(Consumable {})
         .with(Ranged { range: 6 })
         .with(InflictsDamage { damage: 20 })
         .with(AreaOfEffect { radius: 3 })
         .marked::<SimpleMarker<SerializeMe>>()
-        .build();
+        .build()
 }
 
-fn confusion_scroll(ecs: &mut World, x: i32, y: i32) {
+fn confusion_scroll(ecs: &mut World, x: i32, y: i32) -> Entity {
     ecs.create_entity()
         .with(Position { x, y})
         .with(Renderable {
@@ -186,17 +508,22 @@ fn confusion_scroll(ecs: &mut World, x: i32, y: i32) {
             fg: PINK,
             bg: BLACK,
             order: 2,
+            sprite: None,
         })
         .with(Named("Confusion scroll".to_owned()))
+        .with(MakesNoise { volume: 8 })
         .with(Item {})
+        .with(Value { gold: 35 })
+        .with(Weight { lbs: 1 })
+        .with(Stack { count: 1 })
         .with(Consumable {})
         .with(Ranged { range: 6 })
         .with(Confusion { turns: 4 })
         .marked::<SimpleMarker<SerializeMe>>()
-        .build();
+        .build()
 }
 
-fn dagger(ecs: &mut World, x: i32, y: i32) {
+fn dagger(ecs: &mut World, x: i32, y: i32) -> Entity {
     ecs.create_entity()
         .with(Position { x, y})
         .with(Renderable {
@@ -204,16 +531,19 @@ fn dagger(ecs: &mut World, x: i32, y: i32) {
             fg: CYAN,
             bg: BLACK,
             order: 2,
+            sprite: None,
         })
         .with(Named("Dagger".to_owned()))
         .with(Item{})
+        .with(Value { gold: 25 })
+        .with(Weight { lbs: 3 })
         .with(Equippable { slot: EquipmentSlot::MainHand })
         .with(AttackBonus { power: 2 })
         .marked::<SimpleMarker<SerializeMe>>()
-        .build();
+        .build()
 }
 
-fn shield(ecs: &mut World, x: i32, y: i32) {
+fn shield(ecs: &mut World, x: i32, y: i32) -> Entity {
     ecs.create_entity()
         .with(Position { x, y})
         .with(Renderable {
@@ -221,16 +551,19 @@ fn shield(ecs: &mut World, x: i32, y: i32) {
             fg: CYAN,
             bg: BLACK,
             order: 2,
+            sprite: None,
         })
         .with(Named("Shield".to_owned()))
         .with(Item{})
+        .with(Value { gold: 25 })
+        .with(Weight { lbs: 8 })
         .with(Equippable { slot: EquipmentSlot::OffHand })
         .with(DefenseBonus { defense: 1 })
         .marked::<SimpleMarker<SerializeMe>>()
-        .build();
+        .build()
 }
 
-fn longsword(ecs: &mut World, x: i32, y: i32) {
+fn longsword(ecs: &mut World, x: i32, y: i32) -> Entity {
     ecs.create_entity()
         .with(Position { x, y})
         .with(Renderable {
@@ -238,16 +571,19 @@ fn longsword(ecs: &mut World, x: i32, y: i32) {
             fg: YELLOW,
             bg: BLACK,
             order: 2,
+            sprite: None,
         })
         .with(Named("Longsword".to_owned()))
         .with(Item{})
+        .with(Value { gold: 60 })
+        .with(Weight { lbs: 6 })
         .with(Equippable { slot: EquipmentSlot::MainHand })
         .with(AttackBonus { power: 4 })
         .marked::<SimpleMarker<SerializeMe>>()
-        .build();
+        .build()
 }
 
-fn tower_shield(ecs: &mut World, x: i32, y: i32) {
+fn tower_shield(ecs: &mut World, x: i32, y: i32) -> Entity {
     ecs.create_entity()
         .with(Position { x, y})
         .with(Renderable {
@@ -255,16 +591,19 @@ fn tower_shield(ecs: &mut World, x: i32, y: i32) {
             fg: YELLOW,
             bg: BLACK,
             order: 2,
+            sprite: None,
         })
         .with(Named("Tower shield".to_owned()))
         .with(Item{})
+        .with(Value { gold: 60 })
+        .with(Weight { lbs: 15 })
         .with(Equippable { slot: EquipmentSlot::OffHand })
         .with(DefenseBonus { defense: 3 })
         .marked::<SimpleMarker<SerializeMe>>()
-        .build();
+        .build()
 }
 
-fn rations(ecs: &mut World, x: i32, y: i32) {
+fn rations(ecs: &mut World, x: i32, y: i32) -> Entity {
     ecs.create_entity()
         .with(Position { x, y})
         .with(Renderable {
@@ -272,16 +611,20 @@ fn rations(ecs: &mut World, x: i32, y: i32) {
             fg: GREEN,
             bg: BLACK,
             order: 2,
+            sprite: None,
         })
         .with(Named("Rations".to_string()))
         .with(Item {})
+        .with(Value { gold: 8 })
+        .with(Weight { lbs: 2 })
+        .with(Stack { count: 1 })
         .with(Nutritious {})
         .with(Consumable {})
         .marked::<SimpleMarker<SerializeMe>>()
-        .build();
+        .build()
 }
 
-fn magic_mapping_scroll(ecs: &mut World, x: i32, y: i32) {
+fn magic_mapping_scroll(ecs: &mut World, x: i32, y: i32) -> Entity {
     ecs.create_entity()
         .with(Position { x, y})
         .with(Renderable { 
@@ -289,30 +632,163 @@ fn magic_mapping_scroll(ecs: &mut World, x: i32, y: i32) {
             fg: CYAN,
             bg: BLACK,
             order: 2,
+            sprite: None,
         })
         .with(Named("Scroll of Magic Mapping".to_owned()))
+        .with(MakesNoise { volume: 6 })
         .with(Item {})
+        .with(Value { gold: 40 })
+        .with(Weight { lbs: 1 })
+        .with(Stack { count: 1 })
         .with(MagicMapper {})
         .with(Consumable {})
         .marked::<SimpleMarker<SerializeMe>>()
-        .build();
+        .build()
+}
+
+fn recharging_scroll(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y})
+        .with(Renderable { 
+            glyph: to_cp437(')'),
+            fg: YELLOW,
+            bg: BLACK,
+            order: 2,
+            sprite: None,
+        })
+        .with(Named("Scroll of Recharging".to_owned()))
+        .with(MakesNoise { volume: 6 })
+        .with(Item {})
+        .with(Value { gold: 60 })
+        .with(Weight { lbs: 1 })
+        .with(Stack { count: 1 })
+        .with(Recharger {})
+        .with(Consumable {})
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
 }
 
-fn bear_trap(ecs: &mut World, x: i32, y: i32) {
+fn magic_missile_wand(ecs: &mut World, x: i32, y: i32) -> Entity {
+    let charges = thread_rng().gen_range(3..=5);
+    ecs.create_entity()
+        .with(Position { x, y})
+        .with(Renderable {
+            glyph: to_cp437('-'),
+            fg: CYAN,
+            bg: BLACK,
+            order: 2,
+            sprite: None,
+        })
+        .with(Named("Wand of magic missile".to_owned()))
+        .with(MakesNoise { volume: 8 })
+        .with(Item {})
+        .with(Value { gold: 80 })
+        .with(Weight { lbs: 1 })
+        .with(Charges { current: charges, max: charges })
+        .with(Ranged { range: 6 })
+        .with(InflictsDamage { damage: 8 })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+fn confusion_wand(ecs: &mut World, x: i32, y: i32) -> Entity {
+    let charges = thread_rng().gen_range(3..=4);
+    ecs.create_entity()
+        .with(Position { x, y})
+        .with(Renderable {
+            glyph: to_cp437('-'),
+            fg: PINK,
+            bg: BLACK,
+            order: 2,
+            sprite: None,
+        })
+        .with(Named("Wand of confusion".to_owned()))
+        .with(MakesNoise { volume: 8 })
+        .with(Item {})
+        .with(Value { gold: 90 })
+        .with(Weight { lbs: 1 })
+        .with(Charges { current: charges, max: charges })
+        .with(Ranged { range: 6 })
+        .with(Confusion { turns: 4 })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+fn spellbook<'a>(ecs: &'a mut World, x: i32, y: i32, spell: &str, fg: [f32; 4], value: i32, 
+    stats: Spell) -> EntityBuilder<'a> 
+{
+    ecs.create_entity()
+        .with(Position { x, y})
+        .with(Renderable {
+            glyph: to_cp437('¶'),
+            fg,
+            bg: BLACK,
+            order: 2,
+            sprite: None,
+        })
+        .with(Named(format!("Spellbook of {}", spell)))
+        .with(Spellbook { spell: spell.to_owned() })
+        .with(stats)
+        .with(MakesNoise { volume: 6 })
+        .with(Item {})
+        .with(Value { gold: value })
+        .with(Weight { lbs: 3 })
+        .marked::<SimpleMarker<SerializeMe>>()
+}
+
+fn magic_missile_book(ecs: &mut World, x: i32, y: i32) -> Entity {
+    spellbook(ecs, x, y, "Magic Missile", CYAN, 100, 
+        Spell { cost: 3, cooldown: 0, ready_in: 0 })
+        .with(Ranged { range: 6 })
+        .with(InflictsDamage { damage: 6 })
+        .build()
+}
+
+fn fireball_book(ecs: &mut World, x: i32, y: i32) -> Entity {
+    spellbook(ecs, x, y, "Fireball", ORANGE, 200, 
+        Spell { cost: 8, cooldown: 10, ready_in: 0 })
+        .with(Ranged { range: 6 })
+        .with(InflictsDamage { damage: 15 })
+        .with(AreaOfEffect { radius: 2 })
+        .build()
+}
+
+fn confusion_book(ecs: &mut World, x: i32, y: i32) -> Entity {
+    spellbook(ecs, x, y, "Confusion", PINK, 120, 
+        Spell { cost: 4, cooldown: 5, ready_in: 0 })
+        .with(Ranged { range: 6 })
+        .with(Confusion { turns: 3 })
+        .build()
+}
+
+fn healing_book(ecs: &mut World, x: i32, y: i32) -> Entity {
+    spellbook(ecs, x, y, "Healing", GREEN, 150, 
+        Spell { cost: 5, cooldown: 8, ready_in: 0 })
+        .with(ProvidesHealing { heal_amount: 8 })
+        .build()
+}
+
+fn bear_trap(ecs: &mut World, x: i32, y: i32) -> Entity {
+    trap(ecs, x, y, "Bear trap", RED)
+        .with(InflictsDamage { damage: 6 })
+        .with(SingleActivation {})
+        .build()
+}
+
+///A hidden trap that triggers when stepped on
+fn trap<'a>(ecs: &'a mut World, x: i32, y: i32, name: &str, fg: [f32; 4]) -> EntityBuilder<'a> {
     ecs.create_entity()
         .with(Position { x, y })
         .with(Renderable {
             glyph: to_cp437('^'),
-            fg: RED,
+            fg,
             bg: BLACK,
             order: 2,
+            sprite: None,
         })
-        .with(Named("Bear trap".to_owned()))
+        .with(Named(name.to_owned()))
         .with(Hidden {})
         .with(EntryTrigger {})
-        .with(InflictsDamage { damage: 6 })
-        .with(SingleActivation {})
         .marked::<SimpleMarker<SerializeMe>>()
-        .build();
 }
 
@@ -0,0 +1,45 @@
+use std::io::Write;
+use specs::prelude::*;
+use crate::{comp::*, util::GameLog};
+
+///Whether a spell can be cast with the caster's mana, why not otherwise
+pub fn castable(spell: &Spell, mana: Option<&Mana>) -> Result<(), &'static str> {
+    if spell.ready_in > 0 {
+        Err("isn't ready yet")
+    } else if mana.map_or(0, |m| m.current) < spell.cost {
+        Err("needs more mana")
+    } else {
+        Ok(())
+    }
+}
+
+///Reads `book`, which turns into a spell `reader` knows, unless it knows it already
+pub fn learn(ecs: &mut World, reader: Entity, book: Entity) {
+    let spell = match ecs.read_storage::<Spellbook>().get(book) {
+        Some(book) => book.spell.clone(),
+        None => return,
+    };
+    let known = (&ecs.read_storage::<KnownSpell>(), &ecs.read_storage::<Named>()).join()
+        .any(|(k, name)| k.owner == reader && name.0 == spell);
+    let is_player = reader == *ecs.fetch::<Entity>();
+
+    if known {
+        if is_player {
+            write!(ecs.fetch_mut::<GameLog>().new_entry(), "You already know {}.", spell).unwrap();
+        }
+        return;
+    }
+
+    ecs.write_storage::<InBackpack>().remove(book);
+    ecs.write_storage::<Item>().remove(book);
+    ecs.write_storage::<Value>().remove(book);
+    ecs.write_storage::<Weight>().remove(book);
+    ecs.write_storage::<Spellbook>().remove(book);
+    ecs.write_storage::<Named>().insert(book, Named(spell.clone()))
+        .expect("failed to rename spell");
+    ecs.write_storage::<KnownSpell>().insert(book, KnownSpell { owner: reader })
+        .expect("failed to insert KnownSpell");
+    if is_player {
+        write!(ecs.fetch_mut::<GameLog>().new_entry(), "You learn {}.", spell).unwrap();
+    }
+}
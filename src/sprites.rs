@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use serde::Deserialize;
+use specs::prelude::*;
+use crate::{
+    comp::{Named, Renderable},
+    map::TileType,
+    settings::Tileset,
+};
+
+///Sprite indices for map tiles and named entities, read from a tileset description
+#[derive(Debug, Clone, Deserialize)]
+pub struct SpriteSheet {
+    pub path: String,
+    pub sprite_width: u32,
+    pub sprite_height: u32,
+    ///Walls use `walls` instead
+    pub tiles: HashMap<TileType, u16>,
+    ///Wall sprites by the same neighbour mask `wall_mask` gives
+    pub walls: Vec<u16>,
+    ///By `Named`
+    pub entities: HashMap<String, u16>,
+}
+
+impl SpriteSheet {
+    pub fn load(path: &str) -> Self {
+        let data = std::fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("failed to read {}: {}", path, e));
+        let sheet: Self = serde_json::from_str(&data)
+            .unwrap_or_else(|e| panic!("failed to parse {}: {}", path, e));
+        assert!(sheet.walls.len() == 16, "{}: there must be 16 wall sprites", path);
+        sheet
+    }
+
+    ///The sprite image as a tileset for `Screen`
+    pub fn tileset(&self) -> Tileset {
+        Tileset {
+            path: self.path.clone(),
+            glyph_width: self.sprite_width,
+            glyph_height: self.sprite_height,
+            first_glyph: 0,
+            black_background: false,
+        }
+    }
+
+    pub fn entity(&self, name: &str) -> Option<u16> {
+        self.entities.get(name).copied()
+    }
+}
+
+///The loaded sprite sheet, if any, and whether the map is drawn with it
+#[derive(Default)]
+pub struct Sprites {
+    pub sheet: Option<SpriteSheet>,
+    pub enabled: bool,
+}
+
+impl Sprites {
+    pub fn sheet(&self) -> Option<&SpriteSheet> {
+        self.sheet.as_ref().filter(|_| self.enabled)
+    }
+
+    pub fn entity(&self, name: &str) -> Option<u16> {
+        self.sheet.as_ref().and_then(|s| s.entity(name))
+    }
+}
+
+///Gives `e` the sprite for its name
+pub fn assign(ecs: &mut World, e: Entity) {
+    let sprites = ecs.fetch::<Sprites>();
+    let sprite = ecs.read_storage::<Named>().get(e).and_then(|n| sprites.entity(&n.0));
+    if let Some(r) = ecs.write_storage::<Renderable>().get_mut(e) {
+        r.sprite = sprite;
+    }
+}
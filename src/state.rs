@@ -1,5 +1,6 @@
 use std::io::Write;
-use macroquad::prelude::get_frame_time;
+use std::rc::Rc;
+use macroquad::prelude::{get_frame_time, IVec2, Vec2};
 use specs::{prelude::*, saveload::SimpleMarkerAllocator};
 
 use crate::{
@@ -9,16 +10,20 @@ use crate::{
     map_builder::*, 
     player::*, 
     save_load, 
-    screen::Screen, 
+    screen::{Screen, Layer}, 
     spawner::{self, Spawner}, 
     systems::*, 
     util::*,
     draw_map::*,
+    camera::Camera,
+    sprites::{Sprites, SpriteSheet},
+    travel::Travel,
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MapGenFinish {
     NextLevel,
+    PrevLevel,
     Reset,
 }
 
@@ -33,6 +38,7 @@ pub enum RunState {
     UI(UIState),
     Quit,
     NextLevel,
+    PrevLevel,
     GameOver,
     MagicMapReveal { row: i32 },
     GeneratingMap(MapGenFinish),
@@ -43,19 +49,36 @@ pub struct State {
     spawner: Spawner,
     ecs: World,
     dj_system: DjMapUpdateSystem,
+    noise_system: NoiseSystem,
     ai_system: MonsterAI,
-    item_use_system: ItemUseSystem,
+    effect_system: EffectSystem,
     particle_system: ParticleSystem,
-    sorted_drawables: Vec<(Position, Renderable)>,
-    map_builder: Option<Box<dyn MapBuilder>>,
+    ///With the offset each is animated at
+    sorted_drawables: Vec<(Position, Renderable, Vec2)>,
+    map_builder: Option<BuilderChain>,
+    prefabs: Rc<Vec<Prefab>>,
     mapgen_timer: f32,
+    ///Maps thrown away in a row for failing validation
+    mapgen_attempts: u32,
 }
 
-const MAP_WIDTH: i32 = 80;
-const MAP_HEIGHT: i32 = 43;
+pub const MAP_WIDTH: i32 = 100;
+pub const MAP_HEIGHT: i32 = 60;
+///Failed maps regenerated in a row before falling back to a recipe that always passes
+const MAX_MAPGEN_ATTEMPTS: u32 = 5;
+
+///The part of the screen above the UI panel
+fn map_viewport(s: &Screen) -> IRect {
+    IRect::new(0, 0, s.width(), s.height() - gui::PANEL_HEIGHT)
+}
+
+///Levels the player can come back to, with everything left on them
+fn is_persistent(depth: i32) -> bool {
+    depth == 0
+}
 
 impl State {
-    pub fn new(screen: Screen) -> Self {
+    pub fn new(screen: Screen, sprite_sheet: Option<SpriteSheet>, wait_for_animations: bool) -> Self {
         let mut ecs = World::new();
         register_all_components(&mut ecs);
 
@@ -63,42 +86,59 @@ impl State {
         ecs.insert(RunState::UI(UIState::MainMenu(MainMenuSelection::NewGame)));
         ecs.insert(GameLog::default());
         ecs.insert(ParticleBuilder::default());
+        ecs.insert(EffectQueue::default());
         ecs.insert(DeltaTime::default());
         ecs.insert(DjMap::new(40, 40));
+        ecs.insert(NoiseMap::new(MAP_WIDTH, MAP_HEIGHT));
+        ecs.insert(LevelStash::default());
+        ecs.insert(Camera::new(map_viewport(&screen)));
+        ecs.insert(Sprites { sheet: sprite_sheet, enabled: false });
+        ecs.insert(Animations::new(wait_for_animations));
+        ecs.insert(Travel::default());
 
         Self { 
             screen, ecs, 
             dj_system: DjMapUpdateSystem::default(),
+            noise_system: NoiseSystem::default(),
             ai_system: MonsterAI::default(),
-            item_use_system: ItemUseSystem::default(),
+            effect_system: EffectSystem::default(),
             particle_system: ParticleSystem::default(),
             sorted_drawables: vec![],
             spawner: Spawner::new(1),
             map_builder: None,
+            prefabs: Rc::new(load_prefabs("prefabs.json")),
             mapgen_timer: 0.,
+            mapgen_attempts: 0,
         }
     }
 
     fn run_systems(&mut self) {
         self.dj_system.run_now(&self.ecs);
         VisibilitySystem.run_now(&self.ecs);
+        self.noise_system.run_now(&self.ecs);
         self.ai_system.run_now(&self.ecs);
         MapIndexingSystem.run_now(&self.ecs);
         TriggerSystem.run_now(&self.ecs);
         MeleeCombatSystem.run_now(&self.ecs);
-        DamageSystem.run_now(&self.ecs);
         InventorySystem.run_now(&self.ecs);
-        self.item_use_system.run_now(&self.ecs);
+        ItemUseSystem.run_now(&self.ecs);
         ItemDropSystem.run_now(&self.ecs);
-        ParticleSpawnSystem.run_now(&self.ecs);
+        GasSystem.run_now(&self.ecs);
+        self.effect_system.run_now(&self.ecs);
+        ItemSpendSystem.run_now(&self.ecs);
+        DamageSystem.run_now(&self.ecs);
+        EncumbranceSystem.run_now(&self.ecs);
         HungerSystem.run_now(&self.ecs);
+        ManaSystem.run_now(&self.ecs);
 
         self.ecs.maintain();
     }
 
     fn render(&mut self) {
+        if self.screen.fit_to_window() {
+            self.ecs.write_resource::<Camera>().viewport = map_viewport(&self.screen);
+        }
         self.screen.clear();
-        self.particle_system.update(&mut self.ecs);
 
         let state = *self.ecs.fetch::<RunState>();
         match state {
@@ -109,33 +149,76 @@ impl State {
 
         if let RunState::GeneratingMap(_) = state {
             let map = self.map_builder.as_ref().unwrap().intermediate();
-            draw_map(&map, &mut self.screen);
+            let mut camera = *self.ecs.fetch::<Camera>();
+            let (x, y) = map.bounds().center();
+            camera.center_on(x, y, map.bounds());
+            let sprites = self.ecs.fetch::<Sprites>();
+            self.screen.set_layer(Layer::Map);
+            draw_map(&map, &camera, sprites.sheet(), &mut self.screen);
+            self.screen.set_layer(Layer::Ui);
             return;
         }
 
+        AnimationSystem.run_now(&self.ecs);
+        self.particle_system.update(&mut self.ecs);
         let map = self.ecs.fetch::<Map>();
-        draw_map(&*map, &mut self.screen);
+        let plp = *self.ecs.fetch::<IVec2>();
+        let camera = {
+            let mut camera = self.ecs.write_resource::<Camera>();
+            camera.center_on(plp.x, plp.y, map.bounds());
+            *camera
+        };
+        let mut animations = self.ecs.fetch_mut::<Animations>();
+        self.screen.set_scroll(animations.follow_camera(camera.x, camera.y));
+        let sprites = self.ecs.fetch::<Sprites>();
+        self.screen.set_layer(Layer::Map);
+        draw_map(&*map, &camera, sprites.sheet(), &mut self.screen);
         let dm = self.ecs.fetch::<DjMap>();
-        self.screen.draw_djmap(&*dm);
+        self.screen.draw_djmap(&dm, &camera);
 
         let positions = self.ecs.read_storage::<Position>();
         let renderables = self.ecs.read_storage::<Renderable>();
         let hidden = self.ecs.read_storage::<Hidden>();
 
         self.sorted_drawables.clear();
-        self.sorted_drawables.extend((&positions, &renderables, !&hidden)
+        let entities = self.ecs.entities();
+        self.sorted_drawables.extend((&entities, &positions, &renderables, !&hidden)
             .join()
-            .map(|(r, p, _)| (*r, *p))
+            .map(|(e, p, r, _)| (*p, *r, animations.offset(e)))
         );
-        self.sorted_drawables.sort_unstable_by_key(|(_, x)| x.order);
+        self.sorted_drawables.sort_unstable_by_key(|(_, x, _)| x.order);
+
+        self.screen.set_layer(Layer::Entities);
+        for (Position { x, y }, render, offset) in self.sorted_drawables.iter().rev() {
+            if !map.tile_flags(*x, *y).visible { continue; }
+            if let Some((sx, sy)) = camera.to_screen(*x, *y) {
+                match render.sprite.filter(|_| sprites.sheet().is_some()) {
+                    Some(sprite) => 
+                        self.screen.draw_sprite(sx, sy, sprite, render.glyph, colors::WHITE, 
+                            render.bg),
+                    None => self.screen.draw_glyph(sx, sy, render.glyph, render.fg, render.bg),
+                }
+                self.screen.set_offset(sx, sy, *offset);
+            }
+        }
 
-        for (Position { x, y }, render) in self.sorted_drawables.iter().rev() {
-            if map.tile_flags(*x, *y).visible {
-                self.screen.draw_glyph(*x, *y, render.glyph, render.fg, render.bg);
+        self.screen.set_layer(Layer::Effects);
+        self.particle_system.draw(&map, &camera, &mut self.screen);
+        for projectile in animations.projectiles() {
+            let pos = projectile.pos().round();
+            let (x, y) = (pos.x as i32, pos.y as i32);
+            if !map.bounds().contains(x, y) || !map.tile_flags(x, y).visible { continue; }
+            if let Some((sx, sy)) = camera.to_screen(x, y) {
+                self.screen.draw_glyph(sx, sy, projectile.glyph, projectile.fg, colors::BLANK);
+                self.screen.set_offset(sx, sy, projectile.pos() - pos);
             }
         }
 
+        self.screen.set_layer(Layer::Ui);
         gui::draw_ui(&self.ecs, &mut self.screen);
+        if state == RunState::AwaitingInput {
+            gui::draw_tooltip(&self.ecs, &mut self.screen);
+        }
     }
 
     pub fn tick(&mut self) -> bool {
@@ -145,23 +228,28 @@ impl State {
 
         use RunState::*;
         let old_state = *self.ecs.fetch::<RunState>();
+        let animating = matches!(old_state, AwaitingInput | MonsterTurn) 
+            && self.ecs.fetch::<Animations>().blocking();
         let new_state = match old_state {
+            state if animating => state,
             NewGame => self.reset(),
             PreRun => {
                 self.run_systems();
                 AwaitingInput
             },
-            AwaitingInput => handle_input(&mut self.ecs),
+            AwaitingInput => handle_input(&mut self.ecs, self.screen.mouse_cell()),
             PlayerTurn => {
                 self.run_systems();
                 match *self.ecs.fetch::<RunState>() {
                     mmr @ MagicMapReveal { row: _ } => mmr,
+                    NextLevel => NextLevel,
                     _ => MonsterTurn,
                 }
             },
             MonsterTurn => {
                 self.run_systems();
-                AwaitingInput
+                self.spawner.restock_vendors(&mut self.ecs);
+                if movement_lag(&mut self.ecs) { MonsterTurn } else { AwaitingInput }
             }
             SaveGame => {
                 save_load::save_game(&mut self.ecs);
@@ -170,6 +258,7 @@ impl State {
             UI(state) => gui::handle_state(state, &mut self.ecs, &mut self.screen),
             Quit => Quit,
             NextLevel => self.goto_next_level(),
+            PrevLevel => self.goto_prev_level(),
             GameOver => match gui::game_over(&mut self.screen) {
                 GameOverResult::Idle => GameOver,
                 GameOverResult::Quit => RunState::UI(UIState::MainMenu(MainMenuSelection::NewGame))
@@ -189,15 +278,32 @@ impl State {
             },
             GeneratingMap(finish) if self.mapgen_timer < 0. => {
                 self.mapgen_timer = 200.;
-                if self.map_builder.as_mut().unwrap().progress() {
+                let builder = self.map_builder.as_mut().unwrap();
+                if !builder.progress() {
+                    GeneratingMap(finish)
+                } else if !builder.validate_layout().is_empty() 
+                    && self.mapgen_attempts < MAX_MAPGEN_ATTEMPTS 
+                {
+                    self.mapgen_attempts += 1;
+                    let depth = builder.data().depth;
+                    let mut builder = match self.mapgen_attempts {
+                        MAX_MAPGEN_ATTEMPTS => 
+                            fallback_builder(MAP_WIDTH, MAP_HEIGHT, depth, self.prefabs.clone()),
+                        _ => random_builder(MAP_WIDTH, MAP_HEIGHT, depth, self.prefabs.clone()),
+                    };
+                    //Retries aren't animated again
+                    while !builder.progress() {}
+                    self.map_builder = Some(builder);
+                    GeneratingMap(finish)
+                } else {
+                    self.mapgen_attempts = 0;
                     self.gen_world_finish();
                     match finish {
                         MapGenFinish::NextLevel => self.goto_next_level_finish(),
+                        MapGenFinish::PrevLevel => self.goto_prev_level_finish(),
                         MapGenFinish::Reset => (),
                     };
                     PreRun
-                } else {
-                    GeneratingMap(finish)
                 }
             }
             state @ GeneratingMap(_) => {
@@ -214,32 +320,115 @@ impl State {
         new_state != Quit
     }
 
-    fn goto_next_level(&mut self) -> RunState {
+    ///Deletes everything on the current level the player doesn't carry,
+    ///or stashes it if the level is persistent
+    fn leave_level(&mut self) {
+        self.ecs.fetch_mut::<Travel>().cancel();
         let mut to_delete = vec![];
+        let mut to_stash = vec![];
         let player_entity = *self.ecs.fetch::<Entity>();
+        let depth = self.ecs.fetch::<Map>().depth();
+        let persistent = is_persistent(depth);
         {
             let entities = self.ecs.entities();
             let in_backpack = self.ecs.read_storage::<InBackpack>();
             let equipped = self.ecs.read_storage::<Equipped>();
+            let known_spells = self.ecs.read_storage::<KnownSpell>();
+            let stashed = self.ecs.read_storage::<Stashed>();
+            let positions = self.ecs.read_storage::<Position>();
+            let names = self.ecs.read_storage::<Named>();
+            let vendors = self.ecs.read_storage::<Vendor>();
 
             for e in entities.join() {
-                if e == player_entity { continue }
+                if e == player_entity || stashed.contains(e) { continue }
 
                 if let Some(bp) = in_backpack.get(e) {
                     if bp.owner == player_entity { continue }
+                    //Vendor stock stays with the vendor, also while it is stashed away
+                    if vendors.contains(bp.owner) || stashed.contains(bp.owner) 
+                        || (persistent && positions.contains(bp.owner)) 
+                    { 
+                        continue 
+                    }
                 }
                 if let Some(Equipped { slot: _, owner }) = equipped.get(e) {
                     if *owner == player_entity { continue }
                 }
-                to_delete.push(e);
+                if known_spells.get(e).map(|k| k.owner) == Some(player_entity) { continue }
+                match positions.get(e) {
+                    Some(pos) if persistent && names.contains(e) => to_stash.push((e, *pos)),
+                    _ => to_delete.push(e),
+                }
             }
         }
         self.ecs.delete_entities(&to_delete).expect("failed to delete entities");
+
+        if persistent {
+            let mut positions = self.ecs.write_storage::<Position>();
+            let mut stashed = self.ecs.write_storage::<Stashed>();
+            for (e, Position { x, y }) in to_stash {
+                positions.remove(e);
+                stashed.insert(e, Stashed { depth, x, y }).expect("failed to stash entity");
+            }
+            let map = Map::clone(&self.ecs.fetch::<Map>());
+            self.ecs.fetch_mut::<LevelStash>().stash(map);
+        }
+    }
+
+    fn goto_next_level(&mut self) -> RunState {
+        self.leave_level();
         let depth = self.ecs.fetch::<Map>().depth() + 1;
         self.gen_world(depth);
         RunState::GeneratingMap(MapGenFinish::NextLevel)
     }
 
+    fn goto_prev_level(&mut self) -> RunState {
+        self.leave_level();
+        let depth = self.ecs.fetch::<Map>().depth() - 1;
+        let stashed_map = self.ecs.fetch_mut::<LevelStash>().take(depth);
+        match stashed_map {
+            Some(map) => {
+                self.return_to_level(map);
+                self.goto_prev_level_finish();
+                RunState::PreRun
+            },
+            None => {
+                self.gen_world(depth);
+                RunState::GeneratingMap(MapGenFinish::PrevLevel)
+            },
+        }
+    }
+
+    ///Puts a stashed level back together and the player on its down stairs
+    fn return_to_level(&mut self, map: Map) {
+        let depth = map.depth();
+        let plp = down_stairs(&map).unwrap_or(*self.ecs.fetch::<IVec2>());
+        {
+            let entities = self.ecs.entities();
+            let mut positions = self.ecs.write_storage::<Position>();
+            let mut stashed = self.ecs.write_storage::<Stashed>();
+            let restored: Vec<_> = (&entities, &stashed).join()
+                .filter(|(_, s)| s.depth == depth)
+                .map(|(e, s)| (e, Position { x: s.x, y: s.y }))
+                .collect();
+            for (e, pos) in restored {
+                stashed.remove(e);
+                positions.insert(e, pos).expect("failed to unstash entity");
+            }
+        }
+        self.ecs.insert(map);
+        self.place_player(plp);
+    }
+
+    fn goto_prev_level_finish(&mut self) {
+        let stairs = down_stairs(&self.ecs.fetch::<Map>());
+        if let Some(plp) = stairs {
+            self.place_player(plp);
+        }
+        write!(self.ecs.fetch_mut::<GameLog>().new_entry(),
+            "You climb back up.").unwrap();
+    }
+
     fn goto_next_level_finish(&mut self) {
         let player_entity = *self.ecs.fetch::<Entity>();
         let mut stats = self.ecs.write_storage::<CombatStats>();
@@ -259,13 +448,13 @@ impl State {
             write!(log.new_entry(), "Hello world").unwrap();
         }
 
-        self.gen_world(1);
+        self.ecs.insert(LevelStash::default());
+        self.gen_world(0);
         RunState::GeneratingMap(MapGenFinish::Reset)
     }
 
     fn gen_world(&mut self, depth: i32) {
-//        self.map_builder = Some(Box::new(BSPGen::new(MAP_WIDTH, MAP_HEIGHT, depth)));
-         self.map_builder = Some(Box::new(CellularAutomata::new(MAP_WIDTH, MAP_HEIGHT, depth)));
+        self.map_builder = Some(random_builder(MAP_WIDTH, MAP_HEIGHT, depth, self.prefabs.clone()));
     }
 
     fn gen_world_finish(&mut self) {
@@ -273,9 +462,12 @@ impl State {
         builder.spawn(&mut self.ecs, &mut self.spawner);
         let plp = builder.player_pos();
         let map = builder.build();
-        self.ecs.insert(plp);
         self.ecs.insert(map);
+        self.place_player(plp);
+    }
 
+    fn place_player(&mut self, plp: IVec2) {
+        self.ecs.insert(plp);
         if !self.ecs.read_storage::<Player>().is_empty() {
             let player_entity = *self.ecs.fetch::<Entity>();
             *self.ecs.write_storage::<Position>()
@@ -289,3 +481,8 @@ impl State {
     }
 }
 
+fn down_stairs(map: &Map) -> Option<IVec2> {
+    map.bounds().iter()
+        .find(|(x, y)| map.tile(*x, *y) == &TileType::DownStairs)
+        .map(|(x, y)| IVec2::new(x, y))
+}
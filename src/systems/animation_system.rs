@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use macroquad::prelude::Vec2;
+use specs::prelude::*;
+use crate::{
+    comp::Position,
+    map::{Map, ViewMap},
+    util::{DeltaTime, Glyph},
+};
+
+const MOVE_MS: f32 = 80.;
+const BUMP_MS: f32 = 120.;
+///How far toward its target a bump goes, in cells
+const BUMP_REACH: f32 = 0.35;
+const PROJECTILE_MS_PER_TILE: f32 = 25.;
+
+#[derive(Debug, Clone, Copy)]
+struct Tween {
+    elapsed: f32,
+    duration: f32,
+}
+
+impl Tween {
+    fn new(duration: f32) -> Self {
+        Self { elapsed: 0., duration }
+    }
+
+    ///Progress from 0 to 1
+    fn t(&self) -> f32 {
+        (self.elapsed / self.duration).min(1.)
+    }
+
+    fn done(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Projectile {
+    from: Vec2,
+    to: Vec2,
+    pub glyph: Glyph,
+    pub fg: [f32; 4],
+    tween: Tween,
+}
+
+impl Projectile {
+    ///Map position, in fractional tiles
+    pub fn pos(&self) -> Vec2 {
+        self.from + (self.to - self.from) * self.tween.t()
+    }
+}
+
+///Motion drawn between cells, the game itself only ever sees whole tiles
+#[derive(Default)]
+pub struct Animations {
+    ///Input and monster turns hold until animations are done
+    pub wait: bool,
+    ///Where each entity was last seen, to tell when it moved
+    last_pos: HashMap<Entity, Position>,
+    ///Offset an entity starts its move at, it slides to zero
+    moves: HashMap<Entity, (Vec2, Tween)>,
+    bumps: HashMap<Entity, (Vec2, Tween)>,
+    projectiles: Vec<Projectile>,
+    camera: Option<(i32, i32)>,
+    scroll: Option<(Vec2, Tween)>,
+}
+
+impl Animations {
+    pub fn new(wait: bool) -> Self {
+        Self { wait, ..Default::default() }
+    }
+
+    ///Lunges `e` toward `(dx, dy)` and back
+    pub fn bump(&mut self, e: Entity, dx: i32, dy: i32) {
+        let dir = Vec2::new(dx as f32, dy as f32).normalize_or_zero();
+        self.bumps.insert(e, (dir, Tween::new(BUMP_MS)));
+    }
+
+    pub fn projectile(&mut self, from: (i32, i32), to: (i32, i32), glyph: Glyph, fg: [f32; 4]) {
+        let from = Vec2::new(from.0 as f32, from.1 as f32);
+        let to = Vec2::new(to.0 as f32, to.1 as f32);
+        let tiles = (to - from).abs().max_element().max(1.);
+        self.projectiles.push(Projectile {
+            from, to, glyph, fg,
+            tween: Tween::new(tiles * PROJECTILE_MS_PER_TILE),
+        });
+    }
+
+    ///How far from its tile `e` is drawn, in cells
+    pub fn offset(&self, e: Entity) -> Vec2 {
+        let mut offset = Vec2::ZERO;
+        if let Some((from, tween)) = self.moves.get(&e) {
+            offset += *from * (1. - tween.t());
+        }
+        if let Some((dir, tween)) = self.bumps.get(&e) {
+            offset += *dir * BUMP_REACH * (tween.t() * PI).sin();
+        }
+        offset
+    }
+
+    pub fn projectiles(&self) -> &[Projectile] {
+        &self.projectiles
+    }
+
+    ///Records where the camera is and returns how far the map should be drawn shifted,
+    ///so a camera moving by a tile glides there
+    pub fn follow_camera(&mut self, x: i32, y: i32) -> Vec2 {
+        if let Some((cx, cy)) = self.camera {
+            let (dx, dy) = (x - cx, y - cy);
+            if (dx, dy) != (0, 0) {
+                let from = self.scroll_offset() + Vec2::new(dx as f32, dy as f32);
+                self.scroll = match dx.abs().max(dy.abs()) {
+                    1 => Some((from, Tween::new(MOVE_MS))),
+                    _ => None,
+                };
+            }
+        }
+        self.camera = Some((x, y));
+        self.scroll_offset()
+    }
+
+    fn scroll_offset(&self) -> Vec2 {
+        self.scroll.map_or(Vec2::ZERO, |(from, tween)| from * (1. - tween.t()))
+    }
+
+    ///Whether the game should hold off until animations finish
+    pub fn blocking(&self) -> bool {
+        self.wait && !(self.moves.is_empty() && self.bumps.is_empty() && self.projectiles.is_empty())
+    }
+}
+
+///Advances animations and starts a move for everything that stepped to a neighbouring tile in view
+pub struct AnimationSystem;
+
+impl<'a> System<'a> for AnimationSystem {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, DeltaTime>,
+        ReadExpect<'a, Map>,
+        WriteExpect<'a, Animations>,
+        ReadStorage<'a, Position>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, dt, map, mut anims, positions) = data;
+        let anims = &mut *anims;
+        let dt = dt.0;
+
+        for (_, tween) in anims.moves.values_mut().chain(anims.bumps.values_mut()) {
+            tween.elapsed += dt;
+        }
+        anims.moves.retain(|_, (_, tween)| !tween.done());
+        anims.bumps.retain(|_, (_, tween)| !tween.done());
+        for p in anims.projectiles.iter_mut() {
+            p.tween.elapsed += dt;
+        }
+        anims.projectiles.retain(|p| !p.tween.done());
+        if let Some((_, tween)) = &mut anims.scroll {
+            tween.elapsed += dt;
+            if tween.done() { anims.scroll = None; }
+        }
+
+        for (e, pos) in (&entities, &positions).join() {
+            let last = match anims.last_pos.insert(e, *pos) {
+                Some(last) => last,
+                None => continue,
+            };
+            let (dx, dy) = (last.x - pos.x, last.y - pos.y);
+            if (dx, dy) == (0, 0) || dx.abs().max(dy.abs()) > 1 { continue; }
+            let visible = |p: Position| map.bounds().contains(p.x, p.y) 
+                && map.tile_flags(p.x, p.y).visible;
+            if !visible(*pos) && !visible(last) { continue; }
+            let sliding = anims.moves.get(&e)
+                .map_or(Vec2::ZERO, |(from, tween)| *from * (1. - tween.t()));
+            let from = sliding + Vec2::new(dx as f32, dy as f32);
+            anims.moves.insert(e, (from, Tween::new(MOVE_MS)));
+        }
+        anims.last_pos.retain(|e, _| positions.contains(*e) && entities.is_alive(*e));
+        anims.moves.retain(|e, _| entities.is_alive(*e));
+        anims.bumps.retain(|e, _| entities.is_alive(*e));
+    }
+}
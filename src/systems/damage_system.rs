@@ -11,14 +11,18 @@ impl<'a> System<'a> for DamageSystem {
         ReadStorage<'a, Position>,
         WriteStorage<'a, CombatStats>,
         WriteStorage<'a, SufferDamage>,
+        WriteStorage<'a, Asleep>,
+        WriteStorage<'a, Unaware>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
         let (entities, mut map, positions, 
-            mut stats, mut damage) = data;
+            mut stats, mut damage, mut asleep, mut unaware) = data;
 
-        for (entity, mut stats, damage) in (&entities, &mut stats, &damage).join() {
+        for (entity, stats, damage) in (&entities, &mut stats, &damage).join() {
             stats.hp -= damage.amount.iter().sum::<i32>();
+            asleep.remove(entity);
+            unaware.remove(entity);
             if let Some(pos) = positions.get(entity) {
                 map.tile_flags_mut(pos.x, pos.y).bloodstained = true;
             }
@@ -2,7 +2,7 @@ use macroquad::prelude::IVec2;
 use specs::prelude::*;
 use crate::{
     util::DjMap, 
-    map::{Map, ViewMap, TileType},
+    map::{Map, ViewMap},
     alg::BFS,
 };
 
@@ -26,11 +26,11 @@ impl<'a> System<'a> for DjMapUpdateSystem {
 
         let adjacent = |g: &mut DjMap, (x, y): &(i32, i32)| 
             map.adjacent(*x, *y)
-            .filter(|(x, y)| map.tile(*x, *y) == &TileType::Floor 
+            .filter(|(x, y)| map.tile(*x, *y).is_walkable()
                 && g.bounds().contains(*x, *y))
             .collect();
         let sources = [(plp.x, plp.y)];
-        self.bfs.search(sources.into_iter(), &mut *dj_map, 
+        self.bfs.search(sources, &mut *dj_map, 
             |g, (x, y), c| g.set(*x, *y, c), 
             |g, (x, y)| g.get(*x, *y),
             adjacent,
@@ -0,0 +1,286 @@
+use std::io::Write;
+use macroquad::prelude::IVec2;
+use smallvec::SmallVec;
+use specs::prelude::*;
+use rand::{thread_rng, Rng};
+use crate::{
+    state::RunState,
+    comp::*,
+    util::{GameLog, to_cp437, colors::*},
+    map::{Map, ViewMap},
+    alg::compute_fov,
+    systems::ParticleBuilder,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub enum EffectKind {
+    Damage(i32),
+    Healing(i32),
+    Confuse(i32),
+    WellFed,
+    MagicMapping,
+    Recharge,
+    Teleport,
+    Fall,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum EffectTarget {
+    Entity(Entity),
+    Tile(i32, i32),
+    Area { center: (i32, i32), radius: i32 },
+    ///The first creature on the way from `from` to `to`
+    Line { from: (i32, i32), to: (i32, i32) },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Effect {
+    pub kind: EffectKind,
+    pub target: EffectTarget,
+    ///Whoever caused the effect, if anyone
+    pub creator: Option<Entity>,
+    ///The item, spell or trap it came from
+    pub source: Option<Entity>,
+}
+
+///Effects waiting for the `EffectSystem`, any system can push to it
+#[derive(Default)]
+pub struct EffectQueue {
+    effects: Vec<Effect>,
+    ///Sources of applied effects that affected anything, until `ItemSpendSystem` takes them
+    affected: Vec<Entity>,
+}
+
+impl EffectQueue {
+    pub fn push(&mut self, kind: EffectKind, target: EffectTarget,
+        creator: Option<Entity>, source: Option<Entity>)
+    {
+        self.effects.push(Effect { kind, target, creator, source });
+    }
+
+    ///Whether an effect from `source` affected anything
+    pub fn affected(&self, source: Entity) -> bool {
+        self.affected.contains(&source)
+    }
+
+    pub fn clear_affected(&mut self) {
+        self.affected.clear();
+    }
+}
+
+///Applies every queued effect to the entities it targets
+#[derive(Default)]
+pub struct EffectSystem {
+    tiles: SmallVec<[(i32, i32); 256]>,
+    targets: SmallVec<[Entity; 256]>,
+}
+
+impl<'a> System<'a> for EffectSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Map>,
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, GameLog>,
+        WriteExpect<'a, ParticleBuilder>,
+        WriteExpect<'a, RunState>,
+        WriteExpect<'a, EffectQueue>,
+        WriteExpect<'a, IVec2>,
+        ReadStorage<'a, Named>,
+        ReadStorage<'a, Spell>,
+        ReadStorage<'a, InBackpack>,
+        ReadStorage<'a, Renderable>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, Viewshed>,
+        WriteStorage<'a, SufferDamage>,
+        WriteStorage<'a, Confusion>,
+        WriteStorage<'a, CombatStats>,
+        WriteStorage<'a, HungerClock>,
+        WriteStorage<'a, Charges>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, map, player_entity, mut log, mut particle_builder, mut state,
+            mut queue, mut plp, named, spells, backpacks, renderables, 
+            mut positions, mut viewsheds, mut suffer_damage, mut confused, mut stats, mut hunger_clocks, mut charges) = data;
+        let player_entity = *player_entity;
+        let mut rng = thread_rng();
+        let name = |e: Entity| named.get(e).map_or("Something", |n| n.0.as_str());
+        let queue = &mut *queue;
+
+        for effect in queue.effects.drain(..) {
+            self.tiles.clear();
+            self.targets.clear();
+            match effect.target {
+                EffectTarget::Entity(e) => self.targets.push(e),
+                EffectTarget::Tile(x, y) => self.tiles.push((x, y)),
+                EffectTarget::Area { center, radius } => {
+                    compute_fov(IVec2::new(center.0, center.1), radius,
+                        &*map, |tile| self.tiles.push((tile.x, tile.y)));
+                    self.tiles.sort_unstable();
+                    self.tiles.dedup();
+                    for (x, y) in self.tiles.iter().cloned() {
+                        match effect.kind {
+                            EffectKind::Damage(_) => 
+                                particle_builder.burst(x, y, ParticleKind::Fire, YELLOW, 3),
+                            _ => particle_builder.request(x, y, to_cp437('░'), ORANGE, BLACK, 200.),
+                        }
+                    }
+                },
+                EffectTarget::Line { from, to } => {
+                    let glyph = if from.1 == to.1 { '-' } else if from.0 == to.0 { '|' } else { '*' };
+                    for (x, y) in line(from, to) {
+                        particle_builder.request(x, y, to_cp437(glyph), WHITE, BLACK, 150.);
+                        let hit = map.tile_content(x, y).iter().find(|e| stats.contains(**e));
+                        if let Some(hit) = hit {
+                            self.targets.push(*hit);
+                            break;
+                        }
+                    }
+                },
+            };
+            for (x, y) in self.tiles.iter().cloned() {
+                self.targets.extend(map.tile_content(x, y).iter().cloned());
+            }
+
+            let by_player = effect.creator == Some(player_entity);
+            let source = effect.source.map(name);
+            let mut hit = false;
+
+            for target in self.targets.iter().cloned() {
+                let pos = positions.get(target).copied();
+                use EffectKind::*;
+                match effect.kind {
+                    Damage(amount) => {
+                        if !stats.contains(target) { continue; }
+                        SufferDamage::new_damage(&mut suffer_damage, target, amount);
+                        let color = |e: Entity| renderables.get(e).map_or(WHITE, |r| r.fg);
+                        match (by_player, source) {
+                            (true, Some(source)) => {
+                                log.new_entry()
+                                    .text(&format!("You use {} on ", source))
+                                    .colored(name(target), color(target))
+                                    .text(", inflicting ")
+                                    .colored(&amount.to_string(), RED)
+                                    .text(" damage.");
+                            },
+                            (false, Some(source)) if target == player_entity => {
+                                log.new_entry()
+                                    .text(&format!("The {} hits you for ", source))
+                                    .colored(&amount.to_string(), RED)
+                                    .text(" hp.");
+                            },
+                            _ => (),
+                        };
+                        if let Some(pos) = pos {
+                            particle_builder.request(pos.x, pos.y, to_cp437('‼'), RED, BLACK, 200.);
+                            particle_builder.burst(pos.x, pos.y, ParticleKind::Blood, RED, 6);
+                        }
+                    },
+                    Confuse(turns) => {
+                        if !stats.contains(target) { continue; }
+                        confused.insert(target, Confusion { turns })
+                            .expect("failed to insert confusion");
+                        if let (true, Some(source)) = (by_player, source) {
+                            write!(log.new_entry(), "You use {} on {}, confusing them.",
+                                source, name(target)).unwrap();
+                        }
+                        if let Some(pos) = pos {
+                            particle_builder.request(pos.x, pos.y, to_cp437('?'), MAGENTA, BLACK, 200.);
+                        }
+                    },
+                    Healing(amount) => {
+                        let stats = match stats.get_mut(target) {
+                            Some(stats) => stats,
+                            None => continue,
+                        };
+                        stats.hp = stats.max_hp.min(stats.hp + amount);
+                        if let (true, Some(source)) = (target == player_entity, source) {
+                            let cast = effect.source.is_some_and(|s| spells.contains(s));
+                            let verb = if cast { "cast" } else { "drink the" };
+                            write!(log.new_entry(), "You {} {}, healing {} hp.",
+                                verb, source, amount).unwrap();
+                        }
+                        if let Some(pos) = pos {
+                            particle_builder.request(pos.x, pos.y, to_cp437('♥'), GREEN, BLACK, 200.);
+                        }
+                    },
+                    WellFed => {
+                        let hc = match hunger_clocks.get_mut(target) {
+                            Some(hc) => hc,
+                            None => continue,
+                        };
+                        hc.state = HungerState::WellFed;
+                        hc.duration = 20;
+                        if let (true, Some(source)) = (target == player_entity, source) {
+                            write!(log.new_entry(), "You eat the {}.", source).unwrap();
+                        }
+                    },
+                    MagicMapping => {
+                        if target != player_entity { continue; }
+                        write!(log.new_entry(), "The map is revealed to you.").unwrap();
+                        *state = RunState::MagicMapReveal { row: 0 };
+                    },
+                    Recharge => {
+                        for (item, bp, ch) in (&entities, &backpacks, &mut charges).join() {
+                            if bp.owner != target { continue; }
+                            ch.current = ch.max;
+                            if target == player_entity {
+                                write!(log.new_entry(), "The {} is recharged.", name(item)).unwrap();
+                            }
+                        }
+                    },
+                    Teleport => {
+                        let bounds = map.bounds();
+                        let dst = (0..100)
+                            .map(|_| (rng.gen_range(0..bounds.width()), rng.gen_range(0..bounds.height())))
+                            .find(|(x, y)| map.tile(*x, *y).is_walkable() && !map.tile_flags(*x, *y).blocked);
+                        let (pos, dst) = match (positions.get_mut(target), dst) {
+                            (Some(pos), Some(dst)) => (pos, dst),
+                            _ => continue,
+                        };
+                        particle_builder.request(pos.x, pos.y, to_cp437('☼'), CYAN, BLACK, 200.);
+                        pos.x = dst.0;
+                        pos.y = dst.1;
+                        if let Some(viewshed) = viewsheds.get_mut(target) {
+                            viewshed.dirty = true;
+                        }
+                        if target == player_entity {
+                            *plp = IVec2::new(dst.0, dst.1);
+                            write!(log.new_entry(), "You are teleported away!").unwrap();
+                        } else if map.tile_flags(dst.0, dst.1).visible {
+                            write!(log.new_entry(), "{} appears out of thin air.", name(target)).unwrap();
+                        }
+                    },
+                    Fall => {
+                        if !stats.contains(target) { continue; }
+                        if target == player_entity {
+                            write!(log.new_entry(), "You fall through to the level below!").unwrap();
+                            *state = RunState::NextLevel;
+                        } else {
+                            if pos.is_some_and(|pos| map.tile_flags(pos.x, pos.y).visible) {
+                                write!(log.new_entry(), "{} falls out of sight.", name(target)).unwrap();
+                            }
+                            entities.delete(target).expect("failed to delete fallen entity");
+                        }
+                    },
+                }
+                hit = true;
+            }
+
+            if let (true, Some(source)) = (hit, effect.source) {
+                queue.affected.push(source);
+            }
+        }
+    }
+}
+
+///Tiles on the way from `from` to `to`, not including `from`
+fn line(from: (i32, i32), to: (i32, i32)) -> impl Iterator<Item = (i32, i32)> {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let steps = dx.abs().max(dy.abs());
+    (1..=steps).map(move |i| {
+        let t = i as f32 / steps as f32;
+        (from.0 + (dx as f32 * t).round() as i32, from.1 + (dy as f32 * t).round() as i32)
+    })
+}
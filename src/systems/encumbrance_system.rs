@@ -0,0 +1,48 @@
+use std::io::Write;
+use specs::prelude::*;
+use crate::{
+    util::GameLog,
+    comp::*,
+    inventory,
+};
+
+///Marks whoever carries more than their capacity as `Encumbered`
+pub struct EncumbranceSystem;
+
+impl<'a> System<'a> for EncumbranceSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, GameLog>,
+        ReadStorage<'a, CarryCapacity>,
+        ReadStorage<'a, InBackpack>,
+        ReadStorage<'a, Equipped>,
+        ReadStorage<'a, Weight>,
+        ReadStorage<'a, Stack>,
+        WriteStorage<'a, Encumbered>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, player_entity, mut log, capacities, backpacks, 
+            equipped, weights, stacks, mut encumbered) = data;
+
+        for (e, capacity) in (&entities, &capacities).join() {
+            let carried = inventory::carried_weight(e, &backpacks, &equipped, &weights, &stacks);
+            let was_encumbered = encumbered.contains(e);
+
+            if carried > capacity.lbs && !was_encumbered {
+                encumbered.insert(e, Encumbered { lagging: false })
+                    .expect("failed to insert Encumbered");
+                if e == *player_entity {
+                    write!(log.new_entry(), "You are overburdened.").unwrap();
+                }
+            } else if carried <= capacity.lbs && was_encumbered {
+                encumbered.remove(e);
+                if e == *player_entity {
+                    write!(log.new_entry(), "You are no longer overburdened.").unwrap();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use std::collections::HashSet;
+use specs::prelude::*;
+use crate::{
+    state::RunState,
+    comp::*,
+    map::{Map, ViewMap},
+    systems::{EffectQueue, EffectKind, EffectTarget, spawn_gas},
+};
+
+///Gas clouds hurt or confuse whoever stands in them, spread and thin out
+pub struct GasSystem;
+
+impl<'a> System<'a> for GasSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Map>,
+        ReadExpect<'a, RunState>,
+        WriteExpect<'a, EffectQueue>,
+        Read<'a, LazyUpdate>,
+        ReadStorage<'a, Position>,
+        WriteStorage<'a, Gas>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, map, state, mut effects, lazy, positions, mut gases) = data;
+        if *state != RunState::MonsterTurn { return; }
+
+        let mut filled: HashSet<(i32, i32)> = (&positions, &gases).join()
+            .map(|(pos, _)| (pos.x, pos.y))
+            .collect();
+
+        for (e, pos, gas) in (&entities, &positions, &mut gases).join() {
+            let kind = match gas.kind {
+                GasKind::Poison => EffectKind::Damage(2),
+                GasKind::Confusion => EffectKind::Confuse(2),
+            };
+            effects.push(kind, EffectTarget::Tile(pos.x, pos.y), None, Some(e));
+
+            if gas.spread > 0 {
+                for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                    let (x, y) = (pos.x + dx, pos.y + dy);
+                    if !map.bounds().contains(x, y) || !map.tile(x, y).is_walkable() { continue; }
+                    if !filled.insert((x, y)) { continue; }
+                    spawn_gas(&entities, &lazy, x, y, gas.kind, gas.spread - 1);
+                }
+                gas.spread = 0;
+            }
+
+            gas.turns -= 1;
+            if gas.turns <= 0 {
+                entities.delete(e).expect("failed to delete gas");
+            }
+        }
+    }
+}
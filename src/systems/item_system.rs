@@ -1,14 +1,12 @@
 use std::io::Write;
-use macroquad::prelude::IVec2;
 use smallvec::SmallVec;
 use specs::prelude::*;
 use crate::{
-    state::RunState,
     comp::*, 
-    util::{GameLog, to_cp437, colors::*}, 
-    map::Map,
-    alg::compute_fov,
-    systems::ParticleBuilder
+    util::{GameLog, NoiseMap, to_cp437, colors::YELLOW}, 
+    systems::{EffectQueue, EffectKind, EffectTarget, Animations},
+    inventory,
+    spells,
 };
 
 
@@ -21,18 +19,38 @@ impl<'a> System<'a> for InventorySystem {
         ReadExpect<'a, Entity>,
         WriteExpect<'a, GameLog>,
         ReadStorage<'a, Named>,
+        ReadStorage<'a, Gold>,
+        WriteStorage<'a, Wallet>,
         WriteStorage<'a, WantsToPickupItem>,
         WriteStorage<'a, Position>,
         WriteStorage<'a, InBackpack>,
+        WriteStorage<'a, Stack>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (entities, player_entity, mut log, named, mut wants_pickup, mut positions, mut backpacks) = data;
+        let (entities, player_entity, mut log, named, gold, mut wallets, 
+            mut wants_pickup, mut positions, mut backpacks, mut stacks) = data;
 
         for (entity, pickup) in (&entities, &wants_pickup).join() {
             positions.remove(pickup.item);
+            if let (Some(gold), Some(wallet)) = (gold.get(pickup.item), wallets.get_mut(entity)) {
+                wallet.gold += gold.amount;
+                entities.delete(pickup.item).expect("failed to delete gold");
+                if entity == *player_entity {
+                    write!(log.new_entry(), "You pick up {} gold.", gold.amount).unwrap();
+                }
+                continue;
+            }
+
             backpacks.insert(pickup.item, InBackpack { owner: entity })
                 .expect("failed to insert backpack entry");
+            let merge_into = inventory::find_stack(pickup.item, entity, &entities, 
+                &backpacks, &named, &stacks);
+            if let Some(target) = merge_into {
+                let count = stacks.get(pickup.item).unwrap().count;
+                stacks.get_mut(target).unwrap().count += count;
+                entities.delete(pickup.item).expect("failed to delete merged stack");
+            }
             
             if entity == *player_entity {
                 write!(log.new_entry(), "You pick up the {}.", 
@@ -44,177 +62,182 @@ impl<'a> System<'a> for InventorySystem {
     }
 }
 
-#[derive(Default)]
-pub struct ItemUseSystem {
-    aoe_cache: SmallVec<[(i32, i32); 256]>,
-    target_cache: SmallVec<[Entity; 256]>,
-}
+pub struct ItemUseSystem;
 
 impl<'a> System<'a> for ItemUseSystem {
     #[allow(clippy::type_complexity)]
     type SystemData = (
         Entities<'a>,
-        ReadExpect<'a, Map>,
         ReadExpect<'a, Entity>,
         WriteExpect<'a, GameLog>,
-        WriteExpect<'a, ParticleBuilder>,
-        WriteExpect<'a, RunState>,
+        WriteExpect<'a, EffectQueue>,
+        WriteExpect<'a, Animations>,
         ReadStorage<'a, Named>,
         ReadStorage<'a, ProvidesHealing>,
         ReadStorage<'a, InflictsDamage>,
-        ReadStorage<'a, Consumable>,
+        ReadStorage<'a, Confusion>,
         ReadStorage<'a, AreaOfEffect>,
         ReadStorage<'a, Equippable>,
         ReadStorage<'a, Nutritious>,
         ReadStorage<'a, MagicMapper>,
+        ReadStorage<'a, Recharger>,
         ReadStorage<'a, Position>,
-        WriteStorage<'a, Confusion>,
-        WriteStorage<'a, SufferDamage>,
-        WriteStorage<'a, WantsToUseItem>,
-        WriteStorage<'a, CombatStats>,
+        ReadStorage<'a, WantsToUseItem>,
+        ReadStorage<'a, Charges>,
+        ReadStorage<'a, Spell>,
+        ReadStorage<'a, Mana>,
         WriteStorage<'a, Equipped>,
         WriteStorage<'a, InBackpack>,
-        WriteStorage<'a, HungerClock>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (entities, map, player_entity, mut log, mut particle_builder,
-            mut state, named, healers, inflicts_damage, 
-            consumables, aoe, equippable, nutricious,
-            magic_mappers, positions, mut confused, 
-            mut suffer_damage, mut wants_use, mut stats, 
-            mut equipped, mut backpacked, mut hunger_clocks) = data;
+        let (entities, player_entity, mut log, mut effects, mut animations, named, 
+            healers, inflicts_damage, confusion, aoe, equippable, 
+            nutricious, magic_mappers, rechargers, positions, 
+            wants_use, charges, spells, mana, mut equipped, mut backpacked) = data;
         let player_entity = *player_entity;
 
-        for (user, useitem, stats) in (&entities, &wants_use, &mut stats).join() {
-            let mut used = false;
-            self.target_cache.clear();
-
-            if let UseTarget::Point(center) = useitem.target {
-                self.aoe_cache.clear();
-                let is_aoe;
-                if let Some(aoe) = aoe.get(useitem.item) {
-                    compute_fov(IVec2::new(center.0, center.1), aoe.radius, 
-                        &*map, |tile| self.aoe_cache.push((tile.x, tile.y)));
-                    self.aoe_cache.sort_unstable();
-                    self.aoe_cache.dedup();
-                    is_aoe = true;
-                } else {
-                    self.aoe_cache.push(center);
-                    is_aoe = false;
-                }
-                
-                for (x, y) in self.aoe_cache.iter().cloned() {
-                    // if !map.bounds().contains(x, y) { continue; }
-                    for victim in map.tile_content(x, y) {
-                        self.target_cache.push(*victim);
-                    }
-                    if is_aoe {
-                        particle_builder.request(x, y, to_cp437('░'), ORANGE, BLACK, 200.);
-                    }
+        for (user, useitem) in (&entities, &wants_use).join() {
+            let item = useitem.item;
+
+            if let Some(Charges { current: 0, .. }) = charges.get(item) {
+                if user == player_entity {
+                    write!(log.new_entry(), "The {} is out of charges.", 
+                        named.get(item).unwrap().0).unwrap();
                 }
-            } else if let UseTarget::User = useitem.target {
-                self.target_cache.push(user);
+                continue;
             }
 
-            for target in self.target_cache.iter() {
-                if let Some(dmg) = inflicts_damage.get(useitem.item) {
-                    SufferDamage::new_damage(&mut suffer_damage, *target, dmg.damage);
+            if let Some(spell) = spells.get(item) {
+                if let Err(reason) = spells::castable(spell, mana.get(user)) {
                     if user == player_entity {
-                        let target_name = &named.get(*target).unwrap().0;
-                        let item_name = &named.get(useitem.item).unwrap().0;
-                        write!(log.new_entry(), "You use {} on {}, inflicting {} damage.", 
-                            item_name, target_name, dmg.damage).unwrap()
+                        write!(log.new_entry(), "{} {}.", 
+                            named.get(item).unwrap().0, reason).unwrap();
                     }
-                    used = true;
+                    continue;
+                }
+            }
+
+            let target = match useitem.target {
+                UseTarget::User => EffectTarget::Entity(user),
+                UseTarget::Point((x, y)) => match aoe.get(item) {
+                    Some(aoe) => EffectTarget::Area { center: (x, y), radius: aoe.radius },
+                    None => EffectTarget::Tile(x, y),
+                },
+            };
+            let mut queued = false;
+            let mut push = |kind| {
+                effects.push(kind, target, Some(user), Some(item));
+                queued = true;
+            };
 
-                    if let Some(pos) = positions.get(*target) {
-                        particle_builder.request(pos.x, pos.y, to_cp437('‼'), RED, BLACK, 200.);
+            if let Some(dmg) = inflicts_damage.get(item) {
+                push(EffectKind::Damage(dmg.damage));
+            }
+            if let Some(confusion) = confusion.get(item) {
+                push(EffectKind::Confuse(confusion.turns));
+            }
+            if let Some(healer) = healers.get(item) {
+                push(EffectKind::Healing(healer.heal_amount));
+            }
+            if nutricious.contains(item) {
+                push(EffectKind::WellFed);
+            }
+            if magic_mappers.contains(item) {
+                push(EffectKind::MagicMapping);
+            }
+            if rechargers.contains(item) {
+                push(EffectKind::Recharge);
+            }
+
+            if let Some(Equippable { slot }) = equippable.get(item) {
+                let slot = *slot;
+                let mut to_unequip = SmallVec::<[Entity; 4]>::new();
+                for (itm, equipped) in (&entities, &equipped).join() {
+                    if equipped.owner == user && equipped.slot == slot {
+                        to_unequip.push(itm);
+                        if equipped.owner == player_entity {
+                            let name = &named.get(itm).unwrap().0;
+                            write!(log.new_entry(), "You unequip {}.", name).unwrap();
+                        }
                     }
                 }
 
-                if let Some(confusion) = confused.get(useitem.item).cloned() {
-                    confused.insert(*target, confusion).expect("failed to insert confusion");
-                    if user == player_entity {
-                        let target_name = &named.get(*target).unwrap().0;
-                        let item_name = &named.get(useitem.item).unwrap().0;
-                        write!(log.new_entry(), "You use {} on {}, confusing them.",
-                            item_name, target_name).unwrap();
-                    }
-                    used = true;
+                for e in to_unequip {
+                    equipped.remove(e);
+                    backpacked.insert(e, InBackpack { owner: user })
+                        .expect("failed to insert InBackpack");
+                }
 
-                    if let Some(pos) = positions.get(*target) {
-                        particle_builder.request(pos.x, pos.y, to_cp437('?'), MAGENTA, BLACK, 200.);
-                    }
+                backpacked.remove(item).expect("failed to remove InBackpack");
+                equipped.insert(item, Equipped { owner: user, slot })
+                    .expect("failed to insert Equipped");
+                if user == player_entity {
+                    let name = &named.get(item).unwrap().0;
+                    write!(log.new_entry(), "You equip {}.", name).unwrap();
                 }
+            } 
 
-                if let Some(healer) = healers.get(useitem.item) {
-                    used = true;
-                    stats.hp = stats.max_hp.min(stats.hp + healer.heal_amount);
-                    if user == player_entity {
-                        let name = &named.get(useitem.item).unwrap().0;
-                        write!(log.new_entry(), "You drink the {}, healing {} hp.", 
-                            name, healer.heal_amount).unwrap();
-                    }
+            if let (true, Some(pos), UseTarget::Point(to)) = (queued, positions.get(user), useitem.target) {
+                animations.projectile((pos.x, pos.y), to, to_cp437('*'), YELLOW);
+            }
+        }
+    }
+}
 
-                    if let Some(pos) = positions.get(user) {
-                        particle_builder.request(pos.x, pos.y, to_cp437('♥'), GREEN, BLACK, 200.);
-                    }
-                } 
-            
-                if let Some(Equippable { slot }) = equippable.get(useitem.item) {
-                    let slot = *slot;
-                    let mut to_unequip = SmallVec::<[Entity; 4]>::new();
-                    for (itm, equipped) in (&entities, &equipped).join() {
-                        if equipped.owner == *target && equipped.slot == slot {
-                            to_unequip.push(itm);
-                            if equipped.owner == player_entity {
-                                let name = &named.get(itm).unwrap().0;
-                                write!(log.new_entry(), "You unequip {}.", name).unwrap();
-                            }
-                        }
-                    }
+///Spends the items used this turn whose effects affected anything: 
+///charges, mana and cooldowns, and consumables. Runs after the `EffectSystem`
+pub struct ItemSpendSystem;
 
-                    for e in to_unequip {
-                        equipped.remove(e);
-                        backpacked.insert(e, InBackpack { owner: *target })
-                            .expect("failed to insert InBackpack");
-                    }
+impl<'a> System<'a> for ItemSpendSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        Entities<'a>,
+        WriteExpect<'a, EffectQueue>,
+        WriteExpect<'a, NoiseMap>,
+        ReadStorage<'a, Consumable>,
+        ReadStorage<'a, MakesNoise>,
+        ReadStorage<'a, Position>,
+        WriteStorage<'a, WantsToUseItem>,
+        WriteStorage<'a, Stack>,
+        WriteStorage<'a, Charges>,
+        WriteStorage<'a, Spell>,
+        WriteStorage<'a, Mana>,
+    );
 
-                    backpacked.remove(useitem.item).expect("failed to remove InBackpack");
-                    equipped.insert(useitem.item, Equipped { owner: *target, slot })
-                        .expect("failed to insert Equipped");
-                    if *target == player_entity {
-                        let name = &named.get(useitem.item).unwrap().0;
-                        write!(log.new_entry(), "You equip {}.", name).unwrap();
-                    }
-                } 
-
-                if nutricious.contains(useitem.item) {
-                    if let Some(hc) = hunger_clocks.get_mut(user) {
-                        hc.state = HungerState::WellFed;
-                        hc.duration = 20;
-                        if user == player_entity {
-                            let name = &named.get(useitem.item).unwrap().0;
-                            write!(log.new_entry(), "You eat the {}.", name).unwrap();
-                        }
-                    }
-                    used = true;
-                }
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, mut effects, mut noise, consumables, makes_noise, positions, 
+            mut wants_use, mut stacks, mut charges, mut spells, mut mana) = data;
 
-                if magic_mappers.contains(useitem.item) {
-                    write!(log.new_entry(), "The map is revealed to you.").unwrap();
-                    *state = RunState::MagicMapReveal { row: 0 };
-                    used = true;
+        for (user, useitem) in (&entities, &wants_use).join() {
+            let item = useitem.item;
+            if !effects.affected(item) { continue; }
+
+            if let (Some(pos), Some(n)) = (positions.get(user), makes_noise.get(item)) {
+                noise.emit(pos.x, pos.y, n.volume);
+            }
+
+            if let Some(ch) = charges.get_mut(item) {
+                ch.current -= 1;
+            }
+
+            if let Some(spell) = spells.get_mut(item) {
+                spell.ready_in = spell.cooldown;
+                if let Some(mana) = mana.get_mut(user) {
+                    mana.current -= spell.cost;
                 }
             }
 
-            if used && consumables.contains(useitem.item) {
-                entities.delete(useitem.item).expect("delete failed");
+            if consumables.contains(item) {
+                match stacks.get_mut(item) {
+                    Some(stack) if stack.count > 1 => stack.count -= 1,
+                    _ => entities.delete(item).expect("delete failed"),
+                }
             }
         }
 
         wants_use.clear();
+        effects.clear_affected();
     }
 }
 
@@ -0,0 +1,41 @@
+use specs::prelude::*;
+use crate::{
+    state::RunState,
+    comp::*,
+};
+
+const MANA_REGEN_TURNS: i32 = 4;
+
+///Regenerates mana and counts down spell cooldowns, once per turn of their owner
+pub struct ManaSystem;
+
+impl<'a> System<'a> for ManaSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, RunState>,
+        ReadStorage<'a, Player>,
+        ReadStorage<'a, KnownSpell>,
+        WriteStorage<'a, Mana>,
+        WriteStorage<'a, Spell>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, state, players, known, mut mana, mut spells) = data;
+
+        let is_turn = |e: Entity| matches!((*state, players.contains(e)),
+            (RunState::PlayerTurn, true) | (RunState::MonsterTurn, false));
+
+        for (e, mana) in (&entities, &mut mana).join() {
+            if !is_turn(e) { continue; }
+            mana.regen_in -= 1;
+            if mana.regen_in > 0 { continue; }
+            mana.regen_in = MANA_REGEN_TURNS;
+            mana.current = mana.max.min(mana.current + 1);
+        }
+
+        for (known, spell) in (&known, &mut spells).join() {
+            if !is_turn(known.owner) { continue; }
+            spell.ready_in = (spell.ready_in - 1).max(0);
+        }
+    }
+}
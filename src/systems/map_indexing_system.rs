@@ -11,16 +11,20 @@ impl<'a> System<'a> for MapIndexingSystem {
         WriteExpect<'a, Map>,
         ReadStorage<'a, Position>,
         ReadStorage<'a, BlocksTile>,
+        ReadStorage<'a, EntryTrigger>,
+        ReadStorage<'a, Hidden>,
         Entities<'a>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut map, pos, blockers, entities) = data;
+        let (mut map, pos, blockers, triggers, hidden, entities) = data;
 
         map.populate_blocked();
         map.clear_content_index();
         for (entity, pos) in (&entities, &pos).join() {
-            map.tile_flags_mut(pos.x, pos.y).blocked |= blockers.contains(entity);
+            let flags = map.tile_flags_mut(pos.x, pos.y);
+            flags.blocked |= blockers.contains(entity);
+            flags.known_trap |= triggers.contains(entity) && !hidden.contains(entity);
             map.tile_content_mut(pos.x, pos.y).push(entity);
         }
     }
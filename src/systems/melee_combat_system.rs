@@ -1,6 +1,6 @@
 use specs::prelude::*;
-use crate::{comp::*, util::GameLog};
-use super::ParticleBuilder;
+use crate::{comp::*, util::{GameLog, NoiseMap, MELEE_NOISE, colors::{WHITE, RED}}};
+use super::{EffectQueue, EffectKind, EffectTarget, Animations};
 
 
 pub struct MeleeCombatSystem;
@@ -9,7 +9,7 @@ impl<'a> System<'a> for MeleeCombatSystem {
     #[allow(clippy::type_complexity)]
     type SystemData = (
         Entities<'a>,
-        Write<'a, ParticleBuilder>,
+        WriteExpect<'a, EffectQueue>,
         ReadStorage<'a, Named>,
         ReadStorage<'a, CombatStats>,
         ReadStorage<'a, AttackBonus>,
@@ -17,24 +17,25 @@ impl<'a> System<'a> for MeleeCombatSystem {
         ReadStorage<'a, Equipped>,
         ReadStorage<'a, Position>,
         ReadStorage<'a, HungerClock>,
+        ReadStorage<'a, Renderable>,
         WriteExpect<'a, GameLog>,
+        WriteExpect<'a, NoiseMap>,
+        WriteExpect<'a, Animations>,
         WriteStorage<'a, WantsToMelee>,
-        WriteStorage<'a, SufferDamage>
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (entities, mut particle_builder, names, 
+        let (entities, mut effects, names, 
             combat_stats, attack_bonuses, defense_bonuses, 
-            equipped, positions, hunger_clocks, 
-            mut log, mut wants_melee, mut inflict_damage) = data;
+            equipped, positions, hunger_clocks, renderables,
+            mut log, mut noise, mut animations, mut wants_melee) = data;
 
         for (attacker, name, stats, wants_melee, hc) 
             in (&entities, &names, &combat_stats, &mut wants_melee, hunger_clocks.maybe()).join() 
         {
             if stats.hp <= 0 { continue; }
-            if let Some(pos) = positions.get(wants_melee.target) {
-                use crate::util::{colors::*, to_cp437};
-                particle_builder.request(pos.x, pos.y, to_cp437('‼'), ORANGE, BLACK, 100.)
+            if let Some(pos) = positions.get(attacker) {
+                noise.emit(pos.x, pos.y, MELEE_NOISE);
             }
 
             let (mut offensive_bonus, mut defensive_bonus) = (0, 0);
@@ -56,21 +57,29 @@ impl<'a> System<'a> for MeleeCombatSystem {
 
             let target_stats = combat_stats.get(wants_melee.target).unwrap();
             if target_stats.hp <= 0 { continue; }
+            let target_pos = positions.get(wants_melee.target);
+            if let (Some(from), Some(to)) = (positions.get(attacker), target_pos) {
+                animations.bump(attacker, to.x - from.x, to.y - from.y);
+            }
             let target_name = names.get(wants_melee.target).unwrap();
             let damage = stats.power + offensive_bonus 
                 - (target_stats.defense + defensive_bonus);
             
-            use std::io::Write;
+            let color = |e: Entity| renderables.get(e).map_or(WHITE, |r| r.fg);
             let mut entry = log.new_entry();
+            entry.colored(&name.0, color(attacker));
             if damage > 0 {
-                SufferDamage::new_damage(
-                    &mut inflict_damage, 
-                    wants_melee.target, 
-                    damage
-                );
-                write!(entry, "{} hits {} for {} hp.", name.0, target_name.0, damage).unwrap();
+                effects.push(EffectKind::Damage(damage), EffectTarget::Entity(wants_melee.target), 
+                    Some(attacker), None);
+                entry.text(" hits ")
+                    .colored(&target_name.0, color(wants_melee.target))
+                    .text(" for ")
+                    .colored(&damage.to_string(), RED)
+                    .text(" hp.");
             } else {
-                write!(entry, "{} is unable to hurt {}.", name.0, target_name.0).unwrap();
+                entry.text(" is unable to hurt ")
+                    .colored(&target_name.0, color(wants_melee.target))
+                    .text(".");
             }
         }
 
@@ -8,6 +8,12 @@ mod particle_system;
 mod hunger_system;
 mod trigger_system;
 mod djmap_update_system;
+mod noise_system;
+mod encumbrance_system;
+mod mana_system;
+mod effect_system;
+mod gas_system;
+mod animation_system;
 
 pub use visibility_system::*;
 pub use monster_ai::*;
@@ -19,3 +25,9 @@ pub use particle_system::*;
 pub use hunger_system::*;
 pub use trigger_system::*;
 pub use djmap_update_system::*;
+pub use noise_system::*;
+pub use encumbrance_system::*;
+pub use mana_system::*;
+pub use effect_system::*;
+pub use gas_system::*;
+pub use animation_system::*;
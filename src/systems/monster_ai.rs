@@ -1,29 +1,41 @@
+use std::io::Write;
 use macroquad::prelude::IVec2;
+use rand::{thread_rng, Rng, seq::IteratorRandom};
 use specs::prelude::*;
 
 use crate::{
     comp::*, 
     map::{Map, ViewMap}, 
-    util::{IRect, to_cp437, colors::*, DjMap},
+    util::{IRect, to_cp437, colors::*, DjMap, NoiseMap, GameLog},
     state::RunState,
     systems::ParticleBuilder,
+    alg::AStarPath,
 };
 
 #[derive(Default)]
-pub struct MonsterAI;
+pub struct MonsterAI {
+    path: AStarPath,
+}
 
 impl<'a> System<'a> for MonsterAI {
     #[allow(clippy::type_complexity)]
     type SystemData = (
         Entities<'a>,
         ReadExpect<'a, DjMap>,
+        ReadExpect<'a, NoiseMap>,
         ReadExpect<'a, Entity>,
         ReadExpect<'a, IVec2>,
         ReadExpect<'a, RunState>,
         WriteExpect<'a, Map>,
+        WriteExpect<'a, GameLog>,
         WriteExpect<'a, ParticleBuilder>,
         ReadStorage<'a, Monster>,
+        ReadStorage<'a, Npc>,
+        ReadStorage<'a, Named>,
+        WriteStorage<'a, Asleep>,
+        WriteStorage<'a, Unaware>,
         WriteStorage<'a, Confusion>,
+        WriteStorage<'a, IdleBehaviour>,
         WriteStorage<'a, Viewshed>,
         WriteStorage<'a, Position>,
         WriteStorage<'a, WantsToMelee>,
@@ -31,17 +43,36 @@ impl<'a> System<'a> for MonsterAI {
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (entities, dj_map, player, plp, state,
-            mut map, mut particle_builder, monster, 
-            mut confused, mut viewshed, mut pos, 
+        let (entities, dj_map, noise, player, plp, state,
+            mut map, mut log, mut particle_builder, monster, npcs, names,
+            mut asleep, mut unaware, mut confused, mut idle, mut viewshed, mut pos, 
             mut wants_to_melee, mut entity_moved) = data;
 
         match *state {
             RunState::MonsterTurn => (),
             _ => return,
         };
+        let mut rng = thread_rng();
 
         for (entity, viewshed, pos, _) in (&entities, &mut viewshed, &mut pos, &monster).join() {
+            let heard = noise.get(pos.x, pos.y);
+            let in_view = map.tile_flags(pos.x, pos.y).visible;
+            let name = names.get(entity).map(|n| n.0.as_str()).unwrap_or("Something");
+
+            if asleep.contains(entity) {
+                if rng.gen_range(1..=20) + heard <= 20 {
+                    if in_view {
+                        particle_builder.request(pos.x, pos.y, to_cp437('z'), SKYBLUE, BLACK, 200.);
+                    }
+                    continue;
+                }
+                asleep.remove(entity);
+                if in_view {
+                    write!(log.new_entry(), "{} wakes up.", name).unwrap();
+                }
+                continue;
+            }
+
             if let Some(confusion) = confused.get_mut(entity) {
                 confusion.turns -= 1;
                 if confusion.turns <= 0 {
@@ -51,7 +82,17 @@ impl<'a> System<'a> for MonsterAI {
                 continue;
             }
 
-            if viewshed.can_see(plp.x, plp.y) {
+            let sees_player = viewshed.can_see(plp.x, plp.y);
+            if sees_player && unaware.contains(entity) {
+                let d = IVec2::new(pos.x, pos.y) - *plp;
+                let dist = (d.dot(d) as f32).sqrt() as i32;
+                if rng.gen_range(1..=20) + 2 * heard >= 10 + dist {
+                    unaware.remove(entity);
+                    write!(log.new_entry(), "{} notices you!", name).unwrap();
+                    particle_builder.request(pos.x, pos.y, to_cp437('!'), YELLOW, BLACK, 200.);
+                    continue;
+                }
+            } else if sees_player {
                 if IRect::new(pos.x - 1, pos.y - 1, 3, 3).contains(plp.x, plp.y) {
                     wants_to_melee.insert(entity, WantsToMelee { target: *player }).unwrap();
                     continue;
@@ -59,20 +100,76 @@ impl<'a> System<'a> for MonsterAI {
                 let dst = |x: i32, y: i32| (x - plp.x) * (x - plp.x) 
                     + (y - plp.y) * (y - plp.y);
 
+                //Traps the player revealed, see `TileFlags::known_trap`
                 let step = dj_map.adjacent(pos.x, pos.y)
-                    .filter(|(x, y, _)| !map.tile_flags(*x, *y).blocked)
+                    .filter(|(x, y, _)| !map.tile_flags(*x, *y).blocked 
+                        && !map.tile_flags(*x, *y).known_trap)
                     .min_by(|(x1, y1, d1), (x2, y2, d2)| d1.cmp(d2)
                         .then(dst(*x1, *y1).cmp(&dst(*x2, *y2))));
 
                 if let Some((x, y, _)) = step {
-                    map.tile_flags_mut(pos.x, pos.y).blocked = false;
-                    map.tile_flags_mut(x, y).blocked = true;
-                    pos.x = x; pos.y = y;
-                    viewshed.dirty = true;
+                    step_to(&mut map, pos, viewshed, x, y);
                     entity_moved.insert(entity, EntityMoved {}).expect("failed to insert EntityMoved");
                 }
+                continue;
+            }
+
+            if let Some((x, y)) = self.idle_step(&map, pos, idle.get_mut(entity)) {
+                step_to(&mut map, pos, viewshed, x, y);
+                entity_moved.insert(entity, EntityMoved {}).expect("failed to insert EntityMoved");
             }
         }
+
+        //NPCs never hunt anyone, they just go about their business
+        for (entity, viewshed, pos, _) in (&entities, &mut viewshed, &mut pos, &npcs).join() {
+            if let Some((x, y)) = self.idle_step(&map, pos, idle.get_mut(entity)) {
+                step_to(&mut map, pos, viewshed, x, y);
+                entity_moved.insert(entity, EntityMoved {}).expect("failed to insert EntityMoved");
+            }
+        }
+    }
+}
+
+impl MonsterAI {
+    fn idle_step(&mut self, map: &Map, pos: &Position, idle: Option<&mut IdleBehaviour>) 
+        -> Option<(i32, i32)> 
+    {
+        let mut rng = thread_rng();
+        match idle {
+            Some(IdleBehaviour::Wander { area }) => {
+                if rng.gen_bool(0.5) { return None; }
+                map.adjacent(pos.x, pos.y)
+                    .filter(|(x, y)| area.contains(*x, *y))
+                    .choose(&mut rng)
+            },
+            Some(IdleBehaviour::Patrol { waypoints, next }) => {
+                let (x, y) = waypoints[*next];
+                let step = self.path_step(map, pos, x, y);
+                if step.is_none() || step == Some((x, y)) {
+                    *next = (*next + 1) % waypoints.len();
+                }
+                step
+            },
+            Some(IdleBehaviour::Guard { post: (x, y) }) => self.path_step(map, pos, *x, *y),
+            None => None,
+        }
+    }
+
+    fn path_step(&mut self, map: &Map, pos: &Position, x: i32, y: i32) -> Option<(i32, i32)> {
+        if (pos.x, pos.y) == (x, y) { return None; }
+        self.path.compute(map, IVec2::new(pos.x, pos.y), IVec2::new(x, y));
+
+        //The path is reversed and ends with the starting point
+        let path = self.path.result();
+        if path.len() < 2 { return None; }
+        let step = path[path.len() - 2].0;
+        Some((step.x, step.y))
     }
 }
 
+fn step_to(map: &mut Map, pos: &mut Position, viewshed: &mut Viewshed, x: i32, y: i32) {
+    map.tile_flags_mut(pos.x, pos.y).blocked = false;
+    map.tile_flags_mut(x, y).blocked = true;
+    pos.x = x; pos.y = y;
+    viewshed.dirty = true;
+}
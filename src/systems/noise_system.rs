@@ -0,0 +1,59 @@
+use specs::prelude::*;
+use crate::{
+    util::{NoiseMap, Grid, adjacent},
+    map::{Map, ViewMap},
+    state::RunState,
+    alg::BFS,
+};
+
+#[derive(Default)]
+pub struct NoiseSystem {
+    bfs: BFS<(i32, i32)>,
+    dist: Grid<i32>,
+}
+
+impl<'a> System<'a> for NoiseSystem {
+    type SystemData = (
+        ReadExpect<'a, RunState>,
+        ReadExpect<'a, Map>,
+        WriteExpect<'a, NoiseMap>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (state, map, mut noise) = data;
+        let bounds = map.bounds();
+
+        //Whatever was heard during the previous round fades once the player acts again
+        if *state == RunState::PlayerTurn || noise.bounds() != bounds {
+            noise.reset(bounds.width(), bounds.height());
+        }
+        if self.dist.width() != bounds.width() || self.dist.height() != bounds.height() {
+            self.dist = Grid::new(bounds.width(), bounds.height(), -1);
+        }
+
+        for (x, y, volume) in noise.take_sources() {
+            if !bounds.contains(x, y) { continue; }
+            for d in self.dist.iter_mut() { *d = -1; }
+
+            let adjacent = |g: &mut Grid<i32>, (x, y): &(i32, i32)| {
+                if *g.get(*x, *y) >= volume { return Default::default(); }
+                adjacent(*x, *y)
+                    .filter(|(x, y)| bounds.contains(*x, *y) 
+                        && map.tile(*x, *y).is_walkable())
+                    .collect()
+            };
+            self.bfs.search([(x, y)], &mut self.dist,
+                |g, (x, y), c| *g.get_mut(*x, *y) = c,
+                |g, (x, y)| *g.get(*x, *y),
+                adjacent,
+            );
+
+            for (x, y) in bounds.iter() {
+                let d = *self.dist.get(x, y);
+                if d >= 0 {
+                    noise.raise(x, y, volume - d);
+                }
+            }
+        }
+    }
+}
@@ -1,76 +1,171 @@
+use macroquad::prelude::Vec2;
+use rand::{thread_rng, Rng, rngs::ThreadRng};
+use smallvec::{SmallVec, smallvec};
 use specs::prelude::*;
 use crate::{
-    util::DeltaTime,
+    util::{DeltaTime, Glyph, to_cp437, colors::*},
     comp::*,
-    util::Glyph,
+    map::{Map, ViewMap},
+    camera::Camera,
+    screen::Screen,
 };
+use super::Animations;
 
+///How often a flying projectile leaves a trail particle
+const TRAIL_INTERVAL_MS: f32 = 15.;
+
+///A glyph that moves, cycles through glyphs and fades over its lifetime
+#[derive(Debug, Clone)]
+pub struct Particle {
+    ///Map position in fractional tiles
+    pub pos: Vec2,
+    ///Tiles per second
+    pub velocity: Vec2,
+    ///Shown in turn, evenly over the lifetime
+    pub glyphs: SmallVec<[Glyph; 4]>,
+    ///Color at the start and the end of the lifetime
+    pub fg: [[f32; 4]; 2],
+    pub bg: [f32; 4],
+    pub lifetime_ms: f32,
+    pub age_ms: f32,
+}
+
+impl Particle {
+    ///A particle that stays on one tile with one glyph and color
+    pub fn fixed(x: i32, y: i32, glyph: Glyph, fg: [f32; 4], bg: [f32; 4], lifetime_ms: f32) -> Self {
+        Self {
+            pos: Vec2::new(x as f32, y as f32),
+            velocity: Vec2::ZERO,
+            glyphs: smallvec![glyph],
+            fg: [fg, fg],
+            bg, lifetime_ms,
+            age_ms: 0.,
+        }
+    }
+
+    ///A particle of `kind` starting at `(x, y)`, randomized a little
+    pub fn of_kind(kind: ParticleKind, x: f32, y: f32, color: [f32; 4], rng: &mut ThreadRng) -> Self {
+        let fade = |c: [f32; 4]| [c[0], c[1], c[2], 0.];
+        let glyphs = |s: &str| s.chars().map(to_cp437).collect();
+        let (glyphs, velocity, fg, lifetime_ms) = match kind {
+            ParticleKind::Smoke => (
+                glyphs("▒░"),
+                Vec2::new(rng.gen_range(-0.3..0.3), rng.gen_range(-1.0..-0.5)),
+                [[color[0], color[1], color[2], 0.6], fade(color)],
+                rng.gen_range(900. ..1400.),
+            ),
+            ParticleKind::Fire => (
+                glyphs("▲^'."),
+                Vec2::new(rng.gen_range(-0.5..0.5), rng.gen_range(-2.5..-1.0)),
+                [color, fade(RED)],
+                rng.gen_range(300. ..600.),
+            ),
+            ParticleKind::Blood => {
+                let angle = rng.gen_range(0. ..std::f32::consts::TAU);
+                (
+                    glyphs("•∙·"),
+                    Vec2::from_angle(angle) * rng.gen_range(2. ..5.),
+                    [color, fade([color[0] * 0.5, 0., 0., 1.])],
+                    rng.gen_range(200. ..400.),
+                )
+            },
+            ParticleKind::Trail => (
+                glyphs("*+·"),
+                Vec2::new(rng.gen_range(-0.2..0.2), rng.gen_range(-0.2..0.2)),
+                [color, fade(color)],
+                300.,
+            ),
+        };
+        Self { pos: Vec2::new(x, y), velocity, glyphs, fg, bg: BLANK, lifetime_ms, age_ms: 0. }
+    }
+
+    fn glyph(&self) -> Glyph {
+        let i = (self.age_ms / self.lifetime_ms * self.glyphs.len() as f32) as usize;
+        self.glyphs[i.min(self.glyphs.len() - 1)]
+    }
+
+    fn color(&self) -> [f32; 4] {
+        let t = (self.age_ms / self.lifetime_ms).min(1.);
+        let [from, to] = self.fg;
+        [0, 1, 2, 3].map(|i| from[i] + (to[i] - from[i]) * t)
+    }
+}
+
+///Every live particle, kept out of the ecs so there can be a lot of them
 #[derive(Default)]
 pub struct ParticleSystem {
-    buffer: Vec<Entity>,
+    particles: Vec<Particle>,
+    trail_timer_ms: f32,
 }
 
 impl ParticleSystem {
+    ///Takes in requested particles, runs emitters and moves and ages everything
     pub fn update(&mut self, ecs: &mut World) {
         let dt = ecs.fetch::<DeltaTime>().0;
-        for (e, lifetime) in 
-            (&ecs.entities(), &mut ecs.write_storage::<ParticleLifetime>()).join() 
+        let map = ecs.fetch::<Map>();
+        let mut rng = thread_rng();
+
+        self.particles.append(&mut ecs.fetch_mut::<ParticleBuilder>().requests);
+
+        for (pos, emitter) in
+            (&ecs.read_storage::<Position>(), &mut ecs.write_storage::<ParticleEmitter>()).join()
         {
-            lifetime.remaining_ms -= dt;
-            if lifetime.remaining_ms < 0. {
-                self.buffer.push(e);
+            emitter.timer_ms -= dt;
+            while emitter.timer_ms <= 0. {
+                emitter.timer_ms += emitter.interval_ms;
+                if !map.tile_flags(pos.x, pos.y).visible { continue; }
+                self.particles.push(Particle::of_kind(emitter.kind,
+                    pos.x as f32, pos.y as f32, emitter.color, &mut rng));
             }
         }
-        ecs.delete_entities(&self.buffer)
-            .expect("failed to delete particles");
-        self.buffer.clear();
-    }
-}
 
+        self.trail_timer_ms -= dt;
+        if self.trail_timer_ms <= 0. {
+            self.trail_timer_ms = TRAIL_INTERVAL_MS;
+            for projectile in ecs.fetch::<Animations>().projectiles() {
+                let pos = projectile.pos();
+                self.particles.push(Particle::of_kind(ParticleKind::Trail,
+                    pos.x, pos.y, projectile.fg, &mut rng));
+            }
+        }
 
-#[derive(Default, Clone, Copy)]
-pub struct ParticleRequest {
-    pos: Position,
-    r: Renderable,
-    lifetime: ParticleLifetime,
+        for p in self.particles.iter_mut() {
+            p.age_ms += dt;
+            p.pos += p.velocity * dt / 1000.;
+        }
+        self.particles.retain(|p| p.age_ms < p.lifetime_ms);
+    }
+
+    ///Draws the particles on visible tiles to the screen's current layer
+    pub fn draw(&self, map: &Map, camera: &Camera, screen: &mut Screen) {
+        for p in self.particles.iter() {
+            let cell = p.pos.round();
+            let (x, y) = (cell.x as i32, cell.y as i32);
+            if !map.bounds().contains(x, y) || !map.tile_flags(x, y).visible { continue; }
+            if let Some((sx, sy)) = camera.to_screen(x, y) {
+                let pos = Vec2::new(sx as f32, sy as f32) + p.pos - cell;
+                screen.draw_particle(pos, p.glyph(), p.color(), p.bg);
+            }
+        }
+    }
 }
 
+///Particles requested by systems, picked up by `ParticleSystem` on the next frame
 #[derive(Default)]
 pub struct ParticleBuilder {
-    requests: Vec<ParticleRequest>,
+    requests: Vec<Particle>,
 }
 
 impl ParticleBuilder {
     pub fn request(&mut self, x: i32, y: i32, glyph: Glyph, fg: [f32; 4], bg: [f32; 4], lifetime_ms: f32) {
-        self.requests.push(ParticleRequest {
-            pos: Position { x, y },
-            r: Renderable { glyph, fg, bg, order: 0 },
-            lifetime: ParticleLifetime { remaining_ms: lifetime_ms },
-        });
+        self.requests.push(Particle::fixed(x, y, glyph, fg, bg, lifetime_ms));
     }
-}
 
-pub struct ParticleSpawnSystem;
-
-impl<'a> System<'a> for ParticleSpawnSystem {
-    type SystemData = (
-        Entities<'a>,
-        Write<'a, ParticleBuilder>,
-        WriteStorage<'a, Renderable>,
-        WriteStorage<'a, Position>,
-        WriteStorage<'a, ParticleLifetime>,
-    );
-
-    fn run(&mut self, data: Self::SystemData) {
-        let (entities, mut builder, mut renderables, 
-            mut positions, mut lifetimes) = data;
-        for particle in builder.requests.iter() {
-            entities.build_entity()
-                .with(particle.pos, &mut positions)
-                .with (particle.r, &mut renderables)
-                .with (particle.lifetime, &mut lifetimes)
-                .build();
+    ///`count` particles of `kind` from the middle of `(x, y)`
+    pub fn burst(&mut self, x: i32, y: i32, kind: ParticleKind, color: [f32; 4], count: usize) {
+        let mut rng = thread_rng();
+        for _ in 0..count {
+            self.requests.push(Particle::of_kind(kind, x as f32, y as f32, color, &mut rng));
         }
-        builder.requests.clear();
     }
 }
@@ -1,56 +1,148 @@
 use std::io::Write;
+use rand::{thread_rng, Rng, seq::SliceRandom};
 use specs::prelude::*;
 use crate::{
     comp::*,
-    util::{GameLog, to_cp437, colors::*},
-    map::Map,
-    systems::ParticleBuilder,
+    util::{GameLog, NoiseMap, to_cp437, colors::*},
+    map::{Map, ViewMap},
+    systems::{EffectQueue, EffectKind, EffectTarget},
+    sprites,
 };
 
+const GAS_TURNS: i32 = 6;
+const GAS_SPREAD: i32 = 3;
+///A disarm succeeds on d20 + perception of at least this
+const DISARM_DC: i32 = 10;
+
 pub struct TriggerSystem;
 
 impl<'a> System<'a> for TriggerSystem {
+    #[allow(clippy::type_complexity)]
     type SystemData = (
         Entities<'a>,
         ReadExpect<'a, Map>,
         WriteExpect<'a, GameLog>,
-        WriteExpect<'a, ParticleBuilder>,
+        WriteExpect<'a, EffectQueue>,
+        WriteExpect<'a, NoiseMap>,
+        Read<'a, LazyUpdate>,
         ReadStorage<'a, Position>,
         ReadStorage<'a, EntryTrigger>,
         ReadStorage<'a, Named>,
         ReadStorage<'a, InflictsDamage>,
         ReadStorage<'a, SingleActivation>,
+        ReadStorage<'a, MakesNoise>,
+        ReadStorage<'a, Teleporter>,
+        ReadStorage<'a, Pit>,
+        ReadStorage<'a, FiresArrows>,
+        ReadStorage<'a, SpawnsGas>,
+        ReadStorage<'a, Perception>,
         WriteStorage<'a, EntityMoved>,
         WriteStorage<'a, Hidden>,
-        WriteStorage<'a, SufferDamage>,
+        WriteStorage<'a, WantsToDisarm>,
+        WriteStorage<'a, Wallet>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (entities, map, mut log, mut particle_builder,
+        let (entities, map, mut log, mut effects, mut noise, lazy,
             positions, entry_triggers, names, 
-            inflicts_damage, single_activation, mut entity_moved, 
-            mut hiddens, mut suffer_damage) = data;
+            inflicts_damage, single_activation, makes_noise, teleporters,
+            pits, fires_arrows, spawns_gas, perception, mut entity_moved, 
+            mut hiddens, mut wants_to_disarm, mut wallets) = data;
+        let mut rng = thread_rng();
+        let mut triggered = Vec::new();
+
+        for (actor, disarm) in (&entities, &wants_to_disarm).join() {
+            let trap = disarm.trap;
+            if !entities.is_alive(trap) { continue; }
+            let name = names.get(trap).map_or("trap", |n| n.0.as_str());
+            let bonus = perception.get(actor).map_or(0, |p| p.bonus);
+            if rng.gen_range(1..=20) + bonus < DISARM_DC {
+                write!(log.new_entry(), "You fumble with the {}.", name).unwrap();
+                triggered.push((actor, trap));
+                continue;
+            }
+            let gold = rng.gen_range(1..=5) * map.depth().max(1);
+            write!(log.new_entry(), "You disarm the {} and salvage {} gold.", name, gold).unwrap();
+            if let Some(wallet) = wallets.get_mut(actor) {
+                wallet.gold += gold;
+            }
+            entities.delete(trap).expect("failed to delete disarmed trap");
+        }
+        wants_to_disarm.clear();
 
         for (actor, pos, _) in (&entities, &positions, &entity_moved).join() {
             for reactor in map.tile_content(pos.x, pos.y) {
                 if actor == *reactor { continue; }
                 if !entry_triggers.contains(*reactor) { continue; }
+                triggered.push((actor, *reactor));
+            }
+        }
+        entity_moved.clear();
 
-                if let Some(name) = names.get(*reactor) {
-                    write!(log.new_entry(), "{} triggers.", &name.0).unwrap();
-                }
-                if let Some(inflicts) = inflicts_damage.get(*reactor) {
-                    particle_builder.request(pos.x, pos.y, to_cp437('‼'), ORANGE, BLACK, 200.);
-                    SufferDamage::new_damage(&mut suffer_damage, actor, inflicts.damage);
-                }
-                if single_activation.contains(*reactor) {
-                    entities.delete(*reactor).expect("failed to delete reactor");
-                }
+        for (actor, reactor) in triggered {
+            let pos = match positions.get(reactor) {
+                Some(pos) => pos,
+                None => continue,
+            };
+            let here = EffectTarget::Entity(actor);
+            let source = Some(reactor);
 
-                hiddens.remove(*reactor);
+            if let Some(name) = names.get(reactor) {
+                write!(log.new_entry(), "{} triggers.", &name.0).unwrap();
+            }
+            if let Some(inflicts) = inflicts_damage.get(reactor) {
+                let target = match fires_arrows.contains(reactor) {
+                    true => arrow_line(&map, pos),
+                    false => here,
+                };
+                effects.push(EffectKind::Damage(inflicts.damage), target, None, source);
+            }
+            if let Some(n) = makes_noise.get(reactor) {
+                noise.emit(pos.x, pos.y, n.volume);
             }
+            if teleporters.contains(reactor) {
+                effects.push(EffectKind::Teleport, here, None, source);
+            }
+            if pits.contains(reactor) {
+                effects.push(EffectKind::Fall, here, None, source);
+            }
+            if let Some(SpawnsGas { kind }) = spawns_gas.get(reactor) {
+                spawn_gas(&entities, &lazy, pos.x, pos.y, *kind, GAS_SPREAD);
+            }
+            if single_activation.contains(reactor) {
+                entities.delete(reactor).expect("failed to delete reactor");
+            }
+
+            hiddens.remove(reactor);
         }
+    }
+}
 
-        entity_moved.clear();
+///A line from a wall in a random direction back through `pos`, 
+///for the arrow to fly along
+fn arrow_line(map: &Map, pos: &Position) -> EffectTarget {
+    let (dx, dy) = *[(1, 0), (-1, 0), (0, 1), (0, -1)].choose(&mut thread_rng()).unwrap();
+    let (mut x, mut y) = (pos.x, pos.y);
+    while map.bounds().contains(x + dx, y + dy) && map.tile(x + dx, y + dy).is_walkable() {
+        x += dx;
+        y += dy;
     }
+    EffectTarget::Line { from: (x + dx, y + dy), to: (pos.x - dx, pos.y - dy) }
+}
+
+pub fn spawn_gas(entities: &Entities, lazy: &LazyUpdate, x: i32, y: i32, kind: GasKind, spread: i32) {
+    let (name, fg) = match kind {
+        GasKind::Poison => ("Poison gas", GREEN),
+        GasKind::Confusion => ("Confusion gas", PINK),
+    };
+    let e = lazy.create_entity(entities)
+        .with(Position { x, y })
+        .with(Renderable { glyph: to_cp437('▒'), fg, bg: BLACK, order: 3, sprite: None })
+        .with(Named(name.to_owned()))
+        .with(Gas { kind, turns: GAS_TURNS, spread })
+        .with(ParticleEmitter { 
+            kind: ParticleKind::Smoke, color: fg, interval_ms: 300., timer_ms: 0. 
+        })
+        .build();
+    lazy.exec_mut(move |ecs| sprites::assign(ecs, e));
 }
@@ -8,6 +8,12 @@ use crate::{
     util::GameLog,
 };
 
+///Chance of spotting a hidden trap is `(1 + perception) / SPOT_DIE` per look
+const SPOT_DIE: i32 = 24;
+///Searching adds this to the roll for traps within `SEARCH_RADIUS` of the player
+const SEARCH_BONUS: i32 = 8;
+const SEARCH_RADIUS: i32 = 2;
+
 pub struct VisibilitySystem;
 
 impl<'a> System<'a> for VisibilitySystem {
@@ -19,14 +25,16 @@ impl<'a> System<'a> for VisibilitySystem {
         ReadStorage<'a, Position>,
         ReadStorage<'a, Player>,
         ReadStorage<'a, Named>,
+        ReadStorage<'a, Perception>,
         WriteStorage<'a, Viewshed>, 
         WriteStorage<'a, Hidden>,
+        WriteStorage<'a, WantsToSearch>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
         let (entities, mut map, mut log,
-            pos, players, names, 
-            mut viewshed, mut hidden) = data;
+            pos, players, names, perception,
+            mut viewshed, mut hidden, mut wants_to_search) = data;
         let mut rng = thread_rng();
 
         for (ent,viewshed,pos) in (&entities, &mut viewshed, &pos).join() {
@@ -39,13 +47,20 @@ impl<'a> System<'a> for VisibilitySystem {
 
             if players.contains(ent) {
                 map.reset_visible_tiles();
+                let perception = perception.get(ent).map_or(0, |p| p.bonus);
+                let searching = wants_to_search.remove(ent).is_some();
                 for (x, y) in &viewshed.visible_tiles {
+                    let near = (x - pos.x).abs().max((y - pos.y).abs()) <= SEARCH_RADIUS;
+                    let bonus = match searching && near {
+                        true => perception + SEARCH_BONUS,
+                        false => perception,
+                    };
                     map.tile_flags_mut(*x, *y).revealed = true;
                     map.tile_flags_mut(*x, *y).visible = true;
 
                     for e in map.tile_content(*x, *y) {
                         if !hidden.contains(*e) { continue; }
-                        if rng.gen_range(1..24) != 1 { continue; }
+                        if rng.gen_range(1..=SPOT_DIE) + bonus < SPOT_DIE { continue; }
 
                         if let Some(name) = names.get(*e) {
                             write!(log.new_entry(), "You spotted a {}.", &name.0).unwrap();
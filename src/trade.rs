@@ -0,0 +1,85 @@
+use std::io::Write;
+use specs::prelude::*;
+use crate::{comp::*, util::GameLog, inventory};
+
+///What a vendor asks for an item
+pub fn buy_price(value: &Value) -> i32 {
+    value.gold
+}
+
+///What a vendor pays for an item
+pub fn sell_price(value: &Value) -> i32 {
+    (value.gold / 2).max(1)
+}
+
+///Hands `item` over from whoever carries it to `buyer`, if `buyer` can afford it
+fn transfer(ecs: &mut World, item: Entity, buyer: Entity, price: i32) -> bool {
+    let mut backpacks = ecs.write_storage::<InBackpack>();
+    let mut wallets = ecs.write_storage::<Wallet>();
+    let seller = match backpacks.get(item) {
+        Some(bp) => bp.owner,
+        None => return false,
+    };
+    match wallets.get_mut(buyer) {
+        Some(wallet) if wallet.gold >= price => wallet.gold -= price,
+        _ => return false,
+    };
+    if let Some(wallet) = wallets.get_mut(seller) {
+        wallet.gold += price;
+    }
+    backpacks.insert(item, InBackpack { owner: buyer })
+        .expect("failed to insert InBackpack");
+    true
+}
+
+fn names(ecs: &World, vendor: Entity, item: Entity) -> (String, String) {
+    let names = ecs.read_storage::<Named>();
+    let name = |e| names.get(e).map_or_else(|| "Someone".to_owned(), |n| n.0.clone());
+    (name(vendor), name(item))
+}
+
+pub fn buy(ecs: &mut World, vendor: Entity, item: Entity) {
+    let player = *ecs.fetch::<Entity>();
+    let price = ecs.read_storage::<Value>().get(item).map_or(0, buy_price);
+    let (_, item_name) = names(ecs, vendor, item);
+
+    let item = inventory::split_stack(ecs, item, 1);
+    if !inventory::can_carry(ecs, player, item) {
+        inventory::restack(ecs, item);
+        write!(ecs.fetch_mut::<GameLog>().new_entry(), "You can't carry any more.").unwrap();
+        return;
+    }
+    let bought = transfer(ecs, item, player, price);
+    inventory::restack(ecs, item);
+    let mut log = ecs.fetch_mut::<GameLog>();
+    if bought {
+        write!(log.new_entry(), "You buy the {} for {} gold.", item_name, price).unwrap();
+    } else {
+        write!(log.new_entry(), "You can't afford the {}.", item_name).unwrap();
+    }
+}
+
+pub fn sell(ecs: &mut World, vendor: Entity, item: Entity) {
+    let price = ecs.read_storage::<Value>().get(item).map(sell_price);
+    let (vendor_name, item_name) = names(ecs, vendor, item);
+
+    let sold = match price {
+        Some(price) => {
+            let item = inventory::split_stack(ecs, item, 1);
+            let sold = transfer(ecs, item, vendor, price);
+            inventory::restack(ecs, item);
+            sold
+        },
+        None => {
+            write!(ecs.fetch_mut::<GameLog>().new_entry(), 
+                "The {} isn't interested in the {}.", vendor_name, item_name).unwrap();
+            return;
+        },
+    };
+    let mut log = ecs.fetch_mut::<GameLog>();
+    if sold {
+        write!(log.new_entry(), "You sell the {} for {} gold.", item_name, price.unwrap()).unwrap();
+    } else {
+        write!(log.new_entry(), "The {} can't afford the {}.", vendor_name, item_name).unwrap();
+    }
+}
@@ -0,0 +1,216 @@
+use std::io::Write;
+use macroquad::prelude::IVec2;
+use smallvec::SmallVec;
+use specs::prelude::*;
+use crate::{
+    comp::*,
+    alg::{AStarPath, BFS},
+    map::{Map, TileType, ViewMap},
+    player::try_move_player,
+    state::RunState,
+    util::{GameLog, Grid, adjacent},
+};
+
+///A trip the player takes one step of each turn: to a chosen tile, or exploring
+#[derive(Default)]
+pub struct Travel {
+    ///Reversed, the next step is last
+    path: Vec<IVec2>,
+    ///Picks the nearest unexplored tile again every step
+    exploring: bool,
+    ///Items in view when the trip started, any other showing up stops it
+    seen: Vec<Entity>,
+    hp: i32,
+    hunger: Option<HungerState>,
+}
+
+impl Travel {
+    pub fn is_active(&self) -> bool {
+        self.exploring || !self.path.is_empty()
+    }
+
+    pub fn cancel(&mut self) {
+        self.path.clear();
+        self.exploring = false;
+    }
+}
+
+///Tiles the player knows are safe to walk over
+fn passable(map: &Map, p: IVec2) -> bool {
+    map.bounds().contains(p.x, p.y) && {
+        let flags = map.tile_flags(p.x, p.y);
+        flags.revealed && !flags.known_trap && map.tile(p.x, p.y).is_walkable()
+    }
+}
+
+///Steps from `from` to `to` over passable tiles, reversed. `to` itself doesn't have to be passable
+fn find_path(map: &Map, from: IVec2, to: IVec2) -> Vec<IVec2> {
+    let mut astar = AStarPath::new();
+    astar.compute_generic(from, to,
+        &mut |a, b| (b - a).abs().max_element() as f32,
+        &mut |p| adjacent(p.x, p.y)
+            .map(|(x, y)| IVec2::new(x, y))
+            .filter(|p| *p == to || passable(map, *p))
+            .map(|p| (p, 1.))
+            .collect::<SmallVec<_>>());
+    //The result is reversed and ends with the starting point
+    let mut path: Vec<IVec2> = astar.result().iter().map(|(p, _)| *p).collect();
+    path.pop();
+    path
+}
+
+///The nearest unrevealed tile that can be reached over passable ones
+fn nearest_unexplored(map: &Map, from: IVec2) -> Option<IVec2> {
+    let bounds = map.bounds();
+    let mut dist = Grid::new(bounds.width(), bounds.height(), -1);
+    BFS::default().search_until([from], &mut dist,
+        |dist, p, d| *dist.get_mut(p.x, p.y) = d,
+        |dist, p| *dist.get(p.x, p.y),
+        |_, p| match map.tile_flags(p.x, p.y).revealed {
+            true => adjacent(p.x, p.y)
+                .map(|(x, y)| IVec2::new(x, y))
+                .filter(|p| bounds.contains(p.x, p.y)
+                    && (!map.tile_flags(p.x, p.y).revealed || passable(map, *p)))
+                .collect(),
+            false => SmallVec::new(),
+        },
+        |_, p| !map.tile_flags(p.x, p.y).revealed)
+}
+
+///Entities with a `T` on tiles the player can see
+fn in_view<T: Component>(ecs: &World) -> Vec<Entity> {
+    let map = ecs.fetch::<Map>();
+    let hidden = ecs.read_storage::<Hidden>();
+    (&ecs.entities(), &ecs.read_storage::<Position>(), &ecs.read_storage::<T>(), !&hidden).join()
+        .filter(|(_, pos, _, _)| map.tile_flags(pos.x, pos.y).visible)
+        .map(|(e, _, _, _)| e)
+        .collect()
+}
+
+fn name(ecs: &World, e: Entity) -> String {
+    ecs.read_storage::<Named>().get(e).map_or_else(|| "something".to_owned(), |n| n.0.clone())
+}
+
+fn player_status(ecs: &World) -> (i32, Option<HungerState>) {
+    let player_entity = *ecs.fetch::<Entity>();
+    let hp = ecs.read_storage::<CombatStats>().get(player_entity).map_or(0, |s| s.hp);
+    let hunger = ecs.read_storage::<HungerClock>().get(player_entity).map(|hc| hc.state);
+    (hp, hunger)
+}
+
+///Starts the trip, unless a monster is in view
+fn set_off(ecs: &mut World, path: Vec<IVec2>, exploring: bool) -> RunState {
+    if let Some(monster) = in_view::<Monster>(ecs).first() {
+        let name = name(ecs, *monster);
+        write!(ecs.fetch_mut::<GameLog>().new_entry(), 
+            "You can't set off with {} in view.", name).unwrap();
+        return RunState::AwaitingInput;
+    }
+    let seen = in_view::<Item>(ecs);
+    let (hp, hunger) = player_status(ecs);
+    *ecs.fetch_mut::<Travel>() = Travel { path, exploring, seen, hp, hunger };
+    step(ecs)
+}
+
+///Sets off toward a revealed tile
+pub fn travel_to(ecs: &mut World, to: IVec2) -> RunState {
+    let plp = *ecs.fetch::<IVec2>();
+    let path = {
+        let map = ecs.fetch::<Map>();
+        if to == plp || !passable(&map, to) {
+            return RunState::AwaitingInput;
+        }
+        find_path(&map, plp, to)
+    };
+    if path.is_empty() {
+        write!(ecs.fetch_mut::<GameLog>().new_entry(), "You can't find a way there.").unwrap();
+        return RunState::AwaitingInput;
+    }
+    set_off(ecs, path, false)
+}
+
+///Sets off toward the nearest stairs of type `stairs` the player has found
+pub fn travel_to_stairs(ecs: &mut World, stairs: TileType) -> RunState {
+    let plp = *ecs.fetch::<IVec2>();
+    let found = {
+        let map = ecs.fetch::<Map>();
+        let bounds = map.bounds();
+        (0..bounds.height())
+            .flat_map(|y| (0..bounds.width()).map(move |x| IVec2::new(x, y)))
+            .filter(|p| map.tile(p.x, p.y) == &stairs && map.tile_flags(p.x, p.y).revealed)
+            .min_by_key(|p| (*p - plp).abs().max_element())
+    };
+    match found {
+        Some(p) if p == plp => RunState::AwaitingInput,
+        Some(p) => travel_to(ecs, p),
+        None => {
+            let which = if stairs == TileType::DownStairs { "down" } else { "up" };
+            write!(ecs.fetch_mut::<GameLog>().new_entry(),
+                "You haven't found the way {} yet.", which).unwrap();
+            RunState::AwaitingInput
+        },
+    }
+}
+
+pub fn explore(ecs: &mut World) -> RunState {
+    set_off(ecs, vec![], true)
+}
+
+///Why the trip should stop, if it should
+fn interruption(ecs: &World, travel: &Travel) -> Option<String> {
+    if let Some(e) = in_view::<Monster>(ecs).first() {
+        return Some(format!("You see {}.", name(ecs, *e)));
+    }
+    if let Some(e) = in_view::<Item>(ecs).into_iter().find(|e| !travel.seen.contains(e)) {
+        return Some(format!("You spot {}.", name(ecs, e)));
+    }
+    let (hp, hunger) = player_status(ecs);
+    if hp < travel.hp {
+        return Some("You are hurt!".to_owned());
+    }
+    let rank = |h: Option<HungerState>| h.map_or(0, |h| h as i32);
+    if rank(hunger) >= HungerState::Hungry as i32 && rank(hunger) > rank(travel.hunger) {
+        return Some("You are getting hungry.".to_owned());
+    }
+    None
+}
+
+///Takes the next step of the trip, unless it was interrupted,
+///the way is blocked or there is nowhere left to go
+pub fn step(ecs: &mut World) -> RunState {
+    let plp = *ecs.fetch::<IVec2>();
+    let stop = |ecs: &mut World, message: Option<&str>| {
+        ecs.fetch_mut::<Travel>().cancel();
+        if let Some(message) = message {
+            write!(ecs.fetch_mut::<GameLog>().new_entry(), "{}", message).unwrap();
+        }
+        RunState::AwaitingInput
+    };
+
+    let reason = interruption(ecs, &ecs.fetch::<Travel>());
+    if let Some(reason) = reason {
+        return stop(ecs, Some(&reason));
+    }
+
+    if ecs.fetch::<Travel>().exploring {
+        let path = {
+            let map = ecs.fetch::<Map>();
+            nearest_unexplored(&map, plp).map(|to| find_path(&map, plp, to))
+        };
+        match path {
+            Some(path) if !path.is_empty() => ecs.fetch_mut::<Travel>().path = path,
+            _ => return stop(ecs, Some("There is nothing left to explore here.")),
+        }
+    }
+
+    let next = ecs.fetch_mut::<Travel>().path.pop();
+    let next = match next {
+        Some(next) => next,
+        None => return stop(ecs, None),
+    };
+    let d = next - plp;
+    if d.abs().max_element() != 1 || ecs.fetch::<Map>().tile_flags(next.x, next.y).blocked {
+        return stop(ecs, None);
+    }
+    try_move_player(d.x, d.y, ecs)
+}
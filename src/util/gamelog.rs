@@ -1,25 +1,117 @@
-use std::io::Cursor;
+use std::io;
+use super::colors::WHITE;
 
-pub const MAX_ENTRY_LEN: usize = 128;
+///Entries kept before the oldest are dropped
+pub const MAX_ENTRIES: usize = 1000;
+
+///A run of text in one color
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub color: [f32; 4],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogEntry {
+    pub spans: Vec<Span>,
+    ///Times in a row it was logged
+    pub count: u32,
+}
+
+impl LogEntry {
+    ///Splits the entry into lines of at most `width` characters, breaking between words
+    pub fn wrap(&self, width: usize) -> Vec<Vec<Span>> {
+        let mut lines = vec![vec![]];
+        let mut col = 0;
+        let count = match self.count {
+            1 => None,
+            n => Some(Span { text: format!(" x{}", n), color: WHITE }),
+        };
+        for span in self.spans.iter().chain(count.iter()) {
+            for word in span.text.split_inclusive(' ') {
+                let len = word.chars().count();
+                if col > 0 && col + len.min(width) > width {
+                    lines.push(vec![]);
+                    col = 0;
+                }
+                let line = lines.last_mut().unwrap();
+                match line.last_mut() {
+                    Some(Span { text, color }) if *color == span.color => text.push_str(word),
+                    _ => line.push(Span { text: word.to_owned(), color: span.color }),
+                }
+                col += len;
+            }
+        }
+        lines
+    }
+}
 
 #[derive(Default)]
 pub struct GameLog {
-    entries: Vec<[u8; MAX_ENTRY_LEN]>,
+    entries: Vec<LogEntry>,
 }
 
 impl GameLog {
-    pub fn new_entry(&mut self) -> Cursor<&mut [u8]> {
-        self.entries.push([0; MAX_ENTRY_LEN]);
-        Cursor::new(&mut self.entries.last_mut().unwrap()[..])
+    ///Text written to the entry is white, colored parts are added with `colored`.
+    ///The entry is logged when the writer is dropped
+    pub fn new_entry(&mut self) -> EntryWriter<'_> {
+        EntryWriter { log: self, spans: vec![] }
     }
 
-    pub fn last_entries(&self, n: usize) -> impl Iterator<Item = &str> {
-        let idx = self.entries.len() - n.min(self.entries.len());
-        self.entries[idx..].iter()
-            .map(|buf| std::str::from_utf8(buf).unwrap())
+    ///Oldest first
+    pub fn entries(&self) -> &[LogEntry] {
+        &self.entries
     }
 
     pub fn clear(&mut self) {
         self.entries.clear();
     }
+
+    fn push(&mut self, spans: Vec<Span>) {
+        if spans.is_empty() { return; }
+        match self.entries.last_mut() {
+            Some(last) if last.spans == spans => last.count += 1,
+            _ => self.entries.push(LogEntry { spans, count: 1 }),
+        }
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.remove(0);
+        }
+    }
+}
+
+pub struct EntryWriter<'a> {
+    log: &'a mut GameLog,
+    spans: Vec<Span>,
+}
+
+impl EntryWriter<'_> {
+    pub fn text(&mut self, text: &str) -> &mut Self {
+        self.colored(text, WHITE)
+    }
+
+    pub fn colored(&mut self, text: &str, color: [f32; 4]) -> &mut Self {
+        match self.spans.last_mut() {
+            Some(last) if last.color == color => last.text.push_str(text),
+            _ => self.spans.push(Span { text: text.to_owned(), color }),
+        }
+        self
+    }
+}
+
+impl io::Write for EntryWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.text(&String::from_utf8_lossy(buf));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for EntryWriter<'_> {
+    fn drop(&mut self) {
+        let spans = std::mem::take(&mut self.spans);
+        self.log.push(spans);
+    }
 }
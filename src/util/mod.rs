@@ -3,6 +3,7 @@ mod irect;
 mod gamelog;
 mod grid;
 mod djmap;
+mod noisemap;
 pub mod colors;
 
 
@@ -11,6 +12,7 @@ pub use irect::*;
 pub use gamelog::*;
 pub use grid::*;
 pub use djmap::*;
+pub use noisemap::*;
 
 use macroquad::prelude::KeyCode;
 
@@ -0,0 +1,56 @@
+use crate::util::{Grid, IRect};
+
+pub const WALK_NOISE: i32 = 6;
+pub const SNEAK_NOISE: i32 = 2;
+pub const MELEE_NOISE: i32 = 10;
+
+pub struct NoiseMap {
+    levels: Grid<i32>,
+    sources: Vec<(i32, i32, i32)>,
+}
+
+impl NoiseMap {
+    pub fn new(width: i32, height: i32) -> Self {
+        Self {
+            levels: Grid::new(width, height, 0),
+            sources: vec![],
+        }
+    }
+
+    pub fn bounds(&self) -> IRect {
+        IRect::new(0, 0, self.levels.width(), self.levels.height())
+    }
+
+    pub fn emit(&mut self, x: i32, y: i32, volume: i32) {
+        if volume > 0 {
+            self.sources.push((x, y, volume));
+        }
+    }
+
+    pub fn take_sources(&mut self) -> Vec<(i32, i32, i32)> {
+        std::mem::take(&mut self.sources)
+    }
+
+    pub fn reset(&mut self, width: i32, height: i32) {
+        if width != self.levels.width() || height != self.levels.height() {
+            self.levels.resize(width, height, 0);
+        }
+        for x in self.levels.iter_mut() {
+            *x = 0;
+        }
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> i32 {
+        if self.bounds().contains(x, y) {
+            *self.levels.get(x, y)
+        } else {
+            0
+        }
+    }
+
+    ///Keeps the loudest of the existing and the new level
+    pub fn raise(&mut self, x: i32, y: i32, level: i32) {
+        let cur = self.levels.get_mut(x, y);
+        *cur = level.max(*cur);
+    }
+}